@@ -1,121 +1,685 @@
-use std::{fs, path::Path, time};
+use std::{collections::BTreeMap, fs, path::Path, time};
 
+use geo::{Area, BooleanOps, Buffer, CoordsIter, MultiPolygon, Polygon};
 use serde_json::json;
 
 use crate::{
-    types::{CountryData, ToCollection, ToFeatures},
+    filter::CountryFilter,
+    report::{CountryReport, Reporter, Stage},
+    types::{
+        json_to_string, BuildArgs, Compression, Config, CountryData, CountryRewriteConfig,
+        GeometryRewriteConfig, JsonFormat, Manifest, ManifestEntry, MarkerValidation,
+        ProcessingConfig, ProjectPaths, SeriesStep, TimelineEvent, ToCollection, ToFeatures,
+    },
     utils::{
-        diff_countries, get_country, is_match, read_config, rewrite_if_some, rewrite_if_some_option,
+        clip_regions, country_ancestors, country_parents, diff_countries, generate_waters,
+        get_country, hash_hex, hashed_file_name, line_states_at, lines_at, merge_countries,
+        read_config, read_land, rewrite_if_some, rewrite_if_some_option, rewrite_tags,
+        snap_coordinates, to_json, unclaimed_land, validate_markers, with_neighbors, CountrySource,
+        ProjectLayers,
     },
 };
-use wax::Glob;
 
-pub fn build() {
-    let config = read_config();
+pub fn build(paths: &ProjectPaths, args: BuildArgs) {
+    let config = read_config(paths);
+
+    for output in &args.outputs {
+        if !config.processing.iter().any(|p| p.is_match(output)) {
+            panic!("No processing item with name or output folder {output}");
+        }
+    }
+
+    for country in &args.countries {
+        if !config.main.layers.contains(country) {
+            panic!("No country {country} in main.layers");
+        }
+    }
+
+    let selected: Vec<_> = config
+        .processing
+        .iter()
+        .filter(|p| args.outputs.is_empty() || args.outputs.iter().any(|o| p.is_match(o)))
+        .collect();
+
+    let with_world =
+        args.countries.is_empty() && selected.iter().any(|p| p.show_unclaimed.unwrap_or(false));
+    let layers = ProjectLayers::load(&config, paths, with_world);
+
+    let mut reporter = Reporter::new(args.format);
 
     let total_time = time::Instant::now();
 
-    for processing_item in config.processing {
-        println!("--- {} ---", processing_item.output_folder);
+    for processing_item in selected {
+        reporter.start_item(
+            processing_item.name.clone(),
+            processing_item.output_folder.clone(),
+        );
 
         let processed_time = time::Instant::now();
 
-        let out_folder = Path::new(&processing_item.output_folder);
+        let mut processing_item = processing_item.clone();
+        if let Some(at) = args.at {
+            processing_item.date = Some(at);
+            processing_item.series = None;
+        }
+        let processing_item = &processing_item;
 
-        let tags = processing_item.tags.unwrap_or(vec![]);
-        let globs: Vec<Glob> = tags.iter().map(|tag| Glob::new(tag).unwrap()).collect();
+        let mut out_folder = paths.resolve(&processing_item.output_folder);
+        // Selective builds must not replace files of the full build
+        if !args.countries.is_empty() {
+            out_folder.push("partial");
+        }
 
-        let mut countries: Vec<CountryData> = vec![];
+        if processing_item.series.is_some() {
+            build_series(
+                &config,
+                processing_item,
+                &layers,
+                paths,
+                &args.countries,
+                &out_folder,
+                &mut reporter,
+            );
+        } else {
+            let (countries, unclaimed) = process(
+                &config,
+                processing_item,
+                &layers,
+                paths,
+                &args.countries,
+                &mut reporter,
+            );
+
+            write_outputs(
+                processing_item,
+                countries,
+                unclaimed,
+                &layers,
+                &out_folder,
+                &mut reporter,
+            );
+        }
 
-        {
-            let dissolved_time = time::Instant::now();
+        reporter.finish_item(processed_time.elapsed());
+    }
 
-            for country_id in &config.main.layers {
-                let country = get_country(country_id.to_owned());
+    reporter.finish(total_time.elapsed());
 
-                if is_match(&country.config.tags, &globs) {
-                    countries.push(country);
-                }
-            }
+    if let Some(report) = args.report {
+        fs::write(
+            report,
+            serde_json::to_string_pretty(&reporter.report).unwrap(),
+        )
+        .unwrap();
+    }
+}
 
-            println!("Dissolved in {:?}", dissolved_time.elapsed());
+fn json_format(processing_item: &ProcessingConfig, default: JsonFormat) -> JsonFormat {
+    processing_item
+        .json_format
+        .unwrap_or(if processing_item.canonical.unwrap_or(false) {
+            JsonFormat::Pretty
+        } else {
+            default
+        })
+}
+
+/// Builds every date of `processing_item.series` into its own subfolder and writes `series.json`.
+/// Dates where no country or line changed reuse the folder of the previous date
+fn build_series(
+    config: &Config,
+    processing_item: &ProcessingConfig,
+    layers: &ProjectLayers,
+    paths: &ProjectPaths,
+    only_countries: &[String],
+    out_folder: &Path,
+    reporter: &mut Reporter,
+) {
+    let filter = CountryFilter::new(&processing_item.selector);
+    let dates = processing_item.series.as_ref().unwrap().dates();
+
+    let mut steps: Vec<SeriesStep> = vec![];
+    let mut previous = None;
+
+    for date in dates {
+        let countries_state: Vec<_> = layers
+            .countries
+            .iter()
+            .filter(|s| filter.is_match(&s.id, &s.config))
+            .map(|s| s.state_at(Some(&date)))
+            .collect();
+        let lines_state = if processing_item.show_lines.unwrap_or(true) {
+            line_states_at(&layers.lines, Some(&date))
+        } else {
+            vec![]
+        };
+        let state = (countries_state, lines_state);
+
+        if let Some((previous_state, folder)) = &previous {
+            if *previous_state == state {
+                steps.push(SeriesStep {
+                    date,
+                    folder: String::clone(folder),
+                });
+                continue;
+            }
         }
 
-        let countries: Vec<CountryData> = {
-            let diff_time = time::Instant::now();
+        let folder = date.to_string();
+        reporter.start_step(date);
 
-            let mut countries = diff_countries(countries);
+        let mut step_item = processing_item.clone();
+        step_item.date = Some(date);
 
-            println!("Diffed in {:?}", diff_time.elapsed());
+        let (countries, unclaimed) =
+            process(config, &step_item, layers, paths, only_countries, reporter);
 
-            countries.iter_mut().for_each(|c| {
-                if !processing_item.show_markers.unwrap_or(true) {
-                    c.markers = vec![];
-                }
+        write_outputs(
+            &step_item,
+            countries,
+            unclaimed,
+            layers,
+            &out_folder.join(&folder),
+            reporter,
+        );
 
-                for country_rewrite in processing_item.countries_rewrite.clone().unwrap_or(vec![]) {
-                    let tags = country_rewrite.tags.unwrap_or(vec![]);
-                    let globs: Vec<Glob> = tags.iter().map(|tag| Glob::new(tag).unwrap()).collect();
-
-                    if is_match(&c.config.tags, &globs) {
-                        rewrite_if_some(country_rewrite.properties.name, &mut c.config.name);
-                        rewrite_if_some(
-                            country_rewrite.properties.description,
-                            &mut c.config.description,
-                        );
-                        rewrite_if_some(
-                            country_rewrite.properties.foundation_date,
-                            &mut c.config.foundation_date,
-                        );
-                        rewrite_if_some(country_rewrite.properties.flag, &mut c.config.flag);
-                        rewrite_if_some_option(
-                            country_rewrite.properties.about,
-                            &mut c.config.about,
-                        );
-                        rewrite_if_some(country_rewrite.properties.fill, &mut c.config.fill);
-                        rewrite_if_some(country_rewrite.properties.stroke, &mut c.config.stroke);
-                        rewrite_if_some_option(country_rewrite.properties.tags, &mut c.config.tags);
-                    }
-                }
-            });
+        steps.push(SeriesStep {
+            date,
+            folder: folder.clone(),
+        });
+        previous = Some((state, folder));
+    }
 
-            countries
-        };
+    reporter.write_file(
+        &out_folder.join("series.json"),
+        to_json(
+            &steps,
+            json_format(processing_item, JsonFormat::Pretty),
+            processing_item.canonical.unwrap_or(false),
+        ),
+        &processing_item.compress.clone().unwrap_or_default(),
+    );
+}
 
-        {
-            let generated_time = time::Instant::now();
-            let countries_json = serde_json::to_string_pretty(&serde_json::Map::from_iter(
-                countries
+/// Writes generated files of processing item and their manifest into `out_folder`
+fn write_outputs(
+    processing_item: &ProcessingConfig,
+    countries: Vec<CountryData>,
+    unclaimed: Vec<Polygon>,
+    layers: &ProjectLayers,
+    out_folder: &Path,
+    reporter: &mut Reporter,
+) {
+    let generated_time = time::Instant::now();
+    let canonical = processing_item.canonical.unwrap_or(false);
+    let format = |default: JsonFormat| json_format(processing_item, default);
+
+    let countries_json = serde_json::Map::from_iter(countries.iter().map(|country| {
+        let mut value = json!(country.config);
+        value["parent"] = json!(country.config.parent);
+        value["children"] = json!(country.children);
+
+        if !country.regions.is_empty() {
+            value["regions"] = serde_json::Value::Object(serde_json::Map::from_iter(
+                country
+                    .regions
                     .iter()
-                    .map(|country| (country.id.clone(), json!(country.config))),
-            ))
-            .unwrap();
+                    .map(|region| (region.id.clone(), json!(region.config))),
+            ));
+        }
 
-            fs::create_dir_all(out_folder).unwrap();
+        (country.id.clone(), value)
+    }));
 
-            fs::write(
-                out_folder.join("geo.geojson"),
-                countries.to_collection().to_string(),
+    let mut collection = countries.to_collection();
+    if processing_item.show_lines.unwrap_or(true) {
+        collection.features.extend(
+            lines_at(
+                &layers.lines,
+                &layers.properties,
+                processing_item.date.as_ref(),
             )
+            .to_features(),
+        );
+    }
+    if processing_item.show_unclaimed.unwrap_or(false) {
+        collection
+            .features
+            .extend(unclaimed.iter().map(|polygon| geojson::Feature {
+                geometry: Some(geojson::Geometry::from(polygon)),
+                properties: Some(geojson::JsonObject::from_iter([
+                    ("type".to_owned(), json!("unclaimed")),
+                    ("area".to_owned(), json!(polygon.unsigned_area())),
+                ])),
+
+                bbox: None,
+                id: None,
+                foreign_members: None,
+            }));
+    }
+
+    snap_coordinates(&mut collection);
+
+    let mut outputs = vec![
+        (
+            "geo.geojson",
+            to_json(&collection, format(JsonFormat::Minified), canonical),
+        ),
+        (
+            "countries.json",
+            to_json(&countries_json, format(JsonFormat::Pretty), canonical),
+        ),
+    ];
+
+    if processing_item.timeline.unwrap_or(false) {
+        outputs.push((
+            "timeline.json",
+            to_json(
+                &timeline(processing_item, &layers.countries),
+                format(JsonFormat::Pretty),
+                canonical,
+            ),
+        ));
+    }
+
+    fs::create_dir_all(out_folder).unwrap();
+
+    let mut manifest = Manifest::default();
+    let compressions = processing_item.compress.clone().unwrap_or_default();
+    let hashed = processing_item.hashed_filenames.unwrap_or(false);
+
+    for (name, contents) in outputs {
+        let hash = hash_hex(contents.as_bytes());
+        let path = if hashed {
+            hashed_file_name(name, &hash)
+        } else {
+            name.to_owned()
+        };
+
+        reporter.write_file(&out_folder.join(&path), &contents, &compressions);
+
+        manifest.files.insert(
+            name.to_owned(),
+            ManifestEntry {
+                path,
+                hash,
+                size: contents.len(),
+            },
+        );
+    }
+
+    // Written last to point at hashed file names
+    if let Some(public) = &processing_item.public {
+        let mut public = public.clone();
+        if hashed {
+            public.geo = replace_file_name(&public.geo, &manifest.files["geo.geojson"].path);
+            public.countries =
+                replace_file_name(&public.countries, &manifest.files["countries.json"].path);
+        }
+
+        let contents = to_json(&public, format(JsonFormat::Minified), canonical);
+        reporter.write_file(&out_folder.join("public.json"), &contents, &compressions);
+
+        manifest.files.insert(
+            "public.json".to_owned(),
+            ManifestEntry {
+                path: "public.json".to_owned(),
+                hash: hash_hex(contents.as_bytes()),
+                size: contents.len(),
+            },
+        );
+    }
+
+    prune_files(out_folder, &manifest);
+
+    reporter.write_file(
+        &out_folder.join("manifest.json"),
+        to_json(&manifest, format(JsonFormat::Pretty), canonical),
+        &compressions,
+    );
+
+    reporter.timing(Stage::Generate, generated_time.elapsed());
+}
+
+/// Replaces the last segment of `url` with `file_name`
+fn replace_file_name(url: &str, file_name: &str) -> String {
+    match url.rsplit_once('/') {
+        Some((base, _)) => format!("{base}/{file_name}"),
+        None => file_name.to_owned(),
+    }
+}
+
+/// Removes files listed in the previous `manifest.json` of `out_folder` which are not a part of
+/// `manifest`, with their compressed copies
+fn prune_files(out_folder: &Path, manifest: &Manifest) {
+    let Ok(previous) = fs::read_to_string(out_folder.join("manifest.json")) else {
+        return;
+    };
+    let Ok(previous) = serde_json::from_str::<Manifest>(&previous) else {
+        return;
+    };
+
+    for entry in previous.files.values() {
+        let is_current = manifest.files.values().any(|e| e.path == entry.path);
+        // Only plain file names are generated, anything else isn't ours to remove
+        let is_file_name = Path::new(&entry.path).file_name() == Some(entry.path.as_ref());
+
+        if is_current || !is_file_name {
+            continue;
+        }
+
+        let compressed = Compression::ALL
+            .iter()
+            .map(|c| format!("{}.{}", entry.path, c.extension()));
+
+        for path in std::iter::once(entry.path.clone()).chain(compressed) {
+            let _ = fs::remove_file(out_folder.join(path));
+        }
+    }
+}
+
+/// Events of all countries of a processing item regardless of its date, sorted by date
+fn timeline(processing_item: &ProcessingConfig, sources: &[CountrySource]) -> Vec<TimelineEvent> {
+    let filter = CountryFilter::new(&processing_item.selector);
+
+    let mut events: Vec<TimelineEvent> = sources
+        .iter()
+        .filter(|s| filter.is_match(&s.id, &s.config))
+        .flat_map(|s| s.timeline())
+        .collect();
+
+    events.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.country.cmp(&b.country)));
+
+    events
+}
+
+/// Parents of countries with `parent` of `countries_rewrite` applied, matched against
+/// properties of country.toml
+fn hierarchy_parents(
+    processing_item: &ProcessingConfig,
+    sources: &[CountrySource],
+) -> BTreeMap<String, Option<String>> {
+    let mut parents = country_parents(sources);
+
+    for rewrite in processing_item.countries_rewrite.iter().flatten() {
+        let Some(parent) = &rewrite.properties.parent else {
+            continue;
+        };
+        let filter = CountryFilter::new(&rewrite.selector);
+
+        for source in sources {
+            if filter.is_match(&source.id, &source.config) {
+                parents.insert(source.id.clone(), Some(parent.clone()));
+            }
+        }
+    }
+
+    parents
+}
+
+/// Loads, dissolves, diffs, validates and rewrites countries of a processing item. Returns them
+/// with land not covered by any country after diffing.
+/// If `only_countries` is not empty, other countries are omitted from the result
+pub fn process(
+    config: &Config,
+    processing_item: &ProcessingConfig,
+    layers: &ProjectLayers,
+    paths: &ProjectPaths,
+    only_countries: &[String],
+    reporter: &mut Reporter,
+) -> (Vec<CountryData>, Vec<Polygon>) {
+    let sources = &layers.countries;
+    let filter = CountryFilter::new(&processing_item.selector);
+
+    let mut countries: Vec<CountryData> = vec![];
+
+    {
+        let dissolved_time = time::Instant::now();
+
+        for source in sources {
+            let date = processing_item.date.as_ref();
+
+            if source.state_at(date).is_some() && filter.is_match(&source.id, &source.config) {
+                let mut country = get_country(source, config, &layers.properties, date);
+
+                if !processing_item.show_regions.unwrap_or(true) {
+                    country.regions = vec![];
+                }
+
+                countries.push(country);
+            }
+        }
+
+        reporter.timing(Stage::Dissolve, dissolved_time.elapsed());
+    }
+
+    let parents = hierarchy_parents(processing_item, sources);
+
+    let ids: Vec<String> = countries.iter().map(|c| c.id.clone()).collect();
+    for country in countries.iter_mut() {
+        country.children = ids
+            .iter()
+            .filter(|id| parents[*id].as_ref() == Some(&country.id))
+            .cloned()
+            .collect();
+    }
+
+    let geometry_rewrites: Vec<(CountryFilter, GeometryRewriteConfig)> = processing_item
+        .geometry_rewrite
+        .clone()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|rewrite| (CountryFilter::new(&rewrite.selector), rewrite))
+        .collect();
+
+    for country in countries.iter_mut() {
+        for (filter, rewrite) in &geometry_rewrites {
+            if !filter.is_match(&country.id, &country.config) {
+                continue;
+            }
+
+            if let Some(path) = &rewrite.replace_land {
+                country.land = read_land(&paths.resolve(path), config);
+            }
+            if let Some(distance) = rewrite.buffer {
+                country.land = country.land.buffer(distance);
+            }
+        }
+    }
+
+    let trimmed: Vec<f64> = countries
+        .iter_mut()
+        .map(|c| match &layers.land_mask {
+            Some(mask) => mask.clip(&mut c.land),
+            None => 0.0,
+        })
+        .collect();
+
+    let vertices_before: Vec<(String, usize, f64)> = countries
+        .iter()
+        .zip(trimmed)
+        .map(|(c, trimmed)| (c.id.clone(), c.land.coords_count(), trimmed))
+        .collect();
+
+    let diff_time = time::Instant::now();
+
+    let mut countries = if only_countries.is_empty() {
+        diff_countries(countries)
+    } else {
+        let mut countries = diff_countries(with_neighbors(countries, only_countries));
+        countries.retain(|c| only_countries.contains(&c.id));
+        countries
+    };
+
+    reporter.timing(Stage::Diff, diff_time.elapsed());
+
+    // Land of omitted countries would be unclaimed in selective builds
+    let unclaimed = if only_countries.is_empty() && processing_item.show_unclaimed.unwrap_or(false)
+    {
+        unclaimed_land(&countries, layers.world_land.as_ref())
+    } else {
+        vec![]
+    };
+
+    clip_regions(&mut countries);
+
+    for country in &countries {
+        let (_, before, trimmed) = vertices_before
+            .iter()
+            .find(|(id, _, _)| id == &country.id)
             .unwrap();
-            fs::write(out_folder.join("countries.json"), countries_json).unwrap();
 
-            if let Some(public) = processing_item.public {
-                let public = serde_json::to_string(&public).unwrap();
-                fs::write(out_folder.join("public.json"), public).unwrap();
+        reporter.country(CountryReport {
+            id: country.id.clone(),
+            date: processing_item.date,
+            vertices_before: *before,
+            vertices_after: country.land.coords_count(),
+            trimmed_area: *trimmed,
+        });
+    }
+
+    {
+        let markers_config = config.markers.clone().unwrap_or_default();
+        let mut problems = validate_markers(&mut countries, &markers_config);
+
+        for country in &countries {
+            for area in country.urban_areas.iter().filter(|a| a.city.is_none()) {
+                problems.push(format!(
+                    "Urban area \"{}\" of country {} has no city or capital marker with the same title or id",
+                    area.properties
+                        .get("title")
+                        .or(area.properties.get("id"))
+                        .map(|v| json_to_string(v.clone()))
+                        .unwrap_or_default(),
+                    country.id
+                ));
             }
+        }
 
-            println!("Generated files in {:?}", generated_time.elapsed());
+        match markers_config.validation {
+            MarkerValidation::Ignore => {}
+            MarkerValidation::Warn => problems
+                .into_iter()
+                .for_each(|problem| reporter.warning(problem)),
+            MarkerValidation::Fail => {
+                if !problems.is_empty() {
+                    panic!("Invalid markers:\n{}", problems.join("\n"));
+                }
+            }
         }
+    }
 
-        let processed = format!("{:?}", processed_time.elapsed());
+    if let Some(level) = processing_item.hierarchy_level {
+        let mut members: BTreeMap<String, Vec<String>> = BTreeMap::new();
 
-        println!(
-            "--- {} {}---\n",
-            processed,
-            "-".repeat(processing_item.output_folder.len() - processed.len())
-        );
+        for country in &countries {
+            let ancestors =
+                country_ancestors(&country.id, &parents).unwrap_or_else(|err| panic!("{err}"));
+
+            if ancestors.len() > level {
+                members
+                    .entry(ancestors[ancestors.len() - level - 1].clone())
+                    .or_default()
+                    .push(country.id.clone());
+            }
+        }
+
+        for (into, ids) in members {
+            // Members of countries which are not included stay separate
+            if countries.iter().any(|c| c.id == into) {
+                countries = merge_countries(countries, &ids, &into);
+            }
+        }
+    }
+
+    for (filter, rewrite) in &geometry_rewrites {
+        if let Some(into) = &rewrite.merge_into {
+            let ids: Vec<String> = countries
+                .iter()
+                .filter(|c| filter.is_match(&c.id, &c.config))
+                .map(|c| c.id.clone())
+                .collect();
+
+            // Selective builds may omit the target or all members of the merge
+            let omitted = !only_countries.is_empty()
+                && (ids.iter().all(|id| id == into) || !countries.iter().any(|c| &c.id == into));
+
+            if !omitted {
+                countries = merge_countries(countries, &ids, into);
+            }
+        }
+
+        if rewrite.hide_land.unwrap_or(false) {
+            countries
+                .iter_mut()
+                .filter(|c| filter.is_match(&c.id, &c.config))
+                .for_each(|c| {
+                    c.land = MultiPolygon::new(vec![]);
+                    c.regions = vec![];
+                    c.urban_areas = vec![];
+                });
+        }
+    }
+
+    if let Some(waters) = &processing_item.waters {
+        let water_layers = waters.water_layers.clone().unwrap_or_else(|| {
+            let default = "nature/water.geojson".to_owned();
+
+            if paths.resolve(&default).exists() {
+                vec![default]
+            } else {
+                vec![]
+            }
+        });
+
+        let sea = water_layers
+            .iter()
+            .fold(MultiPolygon::new(vec![]), |sea, path| {
+                sea.union(&read_land(&paths.resolve(path), config))
+            });
+        // Empty water layers (e.g. created by `cimengine init`) don't clip waters
+        let sea = (!sea.0.is_empty()).then_some(sea);
+
+        generate_waters(&mut countries, waters, sea.as_ref());
     }
 
-    println!("Total time: {:?}", total_time.elapsed());
+    let rewrites: Vec<(CountryFilter, CountryRewriteConfig)> = processing_item
+        .countries_rewrite
+        .clone()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|rewrite| (CountryFilter::new(&rewrite.selector), rewrite))
+        .collect();
+
+    countries.iter_mut().for_each(|c| {
+        if !processing_item.show_markers.unwrap_or(true) {
+            c.markers = vec![];
+        }
+
+        for (filter, country_rewrite) in &rewrites {
+            if filter.is_match(&c.id, &c.config) {
+                let properties = country_rewrite.properties.clone();
+
+                rewrite_if_some(properties.name, &mut c.config.name);
+                rewrite_if_some(properties.description, &mut c.config.description);
+                rewrite_if_some(properties.foundation_date, &mut c.config.foundation_date);
+                rewrite_if_some_option(properties.dissolution_date, &mut c.config.dissolution_date);
+                rewrite_if_some(properties.flag, &mut c.config.flag);
+                rewrite_if_some_option(properties.about, &mut c.config.about);
+                rewrite_if_some(properties.fill, &mut c.config.fill);
+                rewrite_if_some(properties.stroke, &mut c.config.stroke);
+                rewrite_if_some_option(properties.tags, &mut c.config.tags);
+                rewrite_tags(
+                    properties.remove_tags,
+                    properties.add_tags,
+                    &mut c.config.tags,
+                );
+                c.config.extra.extend(properties.extra);
+            }
+        }
+
+        c.config.parent = parents[&c.id].clone();
+    });
+
+    (countries, unclaimed)
 }
@@ -0,0 +1,106 @@
+use std::time;
+
+use crate::{
+    build::process,
+    report::{LogFormat, Reporter},
+    types::ProjectPaths,
+    utils::{
+        country_ancestors, country_parents, find_slivers, read_config, validate_fields,
+        validate_rewrite_fields, ProjectLayers,
+    },
+};
+
+pub fn check(paths: &ProjectPaths, show_config: bool, sliver_area: f64) {
+    let config = read_config(paths);
+
+    if show_config {
+        println!("{}", toml::to_string_pretty(&config).unwrap());
+    }
+
+    let total_time = time::Instant::now();
+
+    let layers = ProjectLayers::load(&config, paths, true);
+    let sources = &layers.countries;
+    let parents = country_parents(sources);
+
+    let problems: Vec<String> = sources
+        .iter()
+        .flat_map(|source| {
+            let id = &source.id;
+            let country = &source.config;
+
+            let mut problems = match &config.fields {
+                Some(fields) => validate_fields(id, country, fields),
+                None => vec![],
+            };
+
+            if let Err(err) = country.foundation() {
+                problems.push(format!("Invalid foundation_date of country {id}: {err}"));
+            }
+            if let Err(err) = country_ancestors(id, &parents) {
+                problems.push(err);
+            }
+
+            problems
+        })
+        .collect();
+
+    if !problems.is_empty() {
+        panic!("Invalid countries:\n{}", problems.join("\n"));
+    }
+
+    if let Some(fields) = &config.fields {
+        let problems: Vec<String> = config
+            .processing
+            .iter()
+            .flat_map(|processing_item| validate_rewrite_fields(processing_item, fields))
+            .collect();
+
+        if !problems.is_empty() {
+            panic!("Invalid processing items:\n{}", problems.join("\n"));
+        }
+    }
+
+    let mut reporter = Reporter::new(LogFormat::Text);
+
+    for processing_item in &config.processing {
+        reporter.start_item(
+            processing_item.name.clone(),
+            processing_item.output_folder.clone(),
+        );
+
+        // Unclaimed land is needed to find slivers
+        let mut processing_item = processing_item.clone();
+        processing_item.show_unclaimed = Some(true);
+
+        // Every step of a series is checked
+        let dates = match &processing_item.series {
+            Some(series) => series.dates().into_iter().map(Some).collect(),
+            None => vec![processing_item.date],
+        };
+
+        for date in dates {
+            if let (Some(date), Some(_)) = (date, &processing_item.series) {
+                reporter.start_step(date);
+            }
+            processing_item.date = date;
+
+            let (countries, unclaimed) = process(
+                &config,
+                &processing_item,
+                &layers,
+                paths,
+                &[],
+                &mut reporter,
+            );
+
+            for sliver in find_slivers(&countries, &unclaimed, sliver_area) {
+                reporter.warning(sliver);
+            }
+
+            println!("Checked {} countries\n", countries.len());
+        }
+    }
+
+    println!("Checked in {:?}", total_time.elapsed());
+}
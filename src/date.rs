@@ -0,0 +1,201 @@
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Date with optional month and day, e.g. `1850`, `1850-03` or `1850-03-01`.
+/// Missing parts are treated as the start of the period when comparing, so `1850` equals
+/// `1850-01-01`
+#[derive(Debug, Clone, Copy)]
+pub struct PartialDate {
+    pub year: i32,
+    pub month: Option<u8>,
+    pub day: Option<u8>,
+}
+
+impl PartialDate {
+    fn key(&self) -> (i32, u8, u8) {
+        (self.year, self.month.unwrap_or(1), self.day.unwrap_or(1))
+    }
+}
+
+impl PartialEq for PartialDate {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for PartialDate {}
+
+impl Hash for PartialDate {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl Ord for PartialDate {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl PartialOrd for PartialDate {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl FromStr for PartialDate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || format!("Invalid date {s}, expected YYYY, YYYY-MM or YYYY-MM-DD");
+
+        // Negative years are BC
+        let (sign, unsigned) = match s.strip_prefix('-') {
+            Some(rest) => (-1, rest),
+            None => (1, s),
+        };
+
+        let mut parts = unsigned.split('-');
+
+        let year: i32 = parts
+            .next()
+            .filter(|y| !y.is_empty() && y.chars().all(|c| c.is_ascii_digit()))
+            .and_then(|y| y.parse().ok())
+            .ok_or_else(invalid)?;
+
+        let mut part = |max: u8| -> Result<Option<u8>, String> {
+            match parts.next() {
+                Some(p) => match p.parse::<u8>() {
+                    Ok(n) if (1..=max).contains(&n) && p.len() <= 2 => Ok(Some(n)),
+                    _ => Err(invalid()),
+                },
+                None => Ok(None),
+            }
+        };
+
+        let month = part(12)?;
+        let day = part(31)?;
+
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+
+        Ok(PartialDate {
+            year: sign * year,
+            month,
+            day,
+        })
+    }
+}
+
+impl fmt::Display for PartialDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}", self.year)?;
+
+        if let Some(month) = self.month {
+            write!(f, "-{:02}", month)?;
+        }
+        if let Some(day) = self.day {
+            write!(f, "-{:02}", day)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Serialize for PartialDate {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Accepts strings, years as integers and toml dates
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawDate {
+    Year(i32),
+    String(String),
+    Toml(toml::value::Datetime),
+}
+
+impl<'de> Deserialize<'de> for PartialDate {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match RawDate::deserialize(deserializer)? {
+            RawDate::Year(year) => Ok(PartialDate {
+                year,
+                month: None,
+                day: None,
+            }),
+            RawDate::String(s) => s.parse().map_err(serde::de::Error::custom),
+            RawDate::Toml(datetime) => match datetime.date {
+                Some(date) => Ok(PartialDate {
+                    year: date.year as i32,
+                    month: Some(date.month),
+                    day: Some(date.day),
+                }),
+                None => Err(serde::de::Error::custom("Expected date, got time")),
+            },
+        }
+    }
+}
+
+impl JsonSchema for PartialDate {
+    fn schema_name() -> String {
+        "PartialDate".to_owned()
+    }
+
+    /// Same forms as accepted by [`RawDate`]
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        serde_json::from_value(json!({
+            "description": "Year or date in YYYY, YYYY-MM or YYYY-MM-DD format",
+            "anyOf": [
+                { "type": "integer" },
+                { "type": "string", "pattern": "^-?[0-9]+(-[0-9]{1,2}(-[0-9]{1,2})?)?$" },
+                { "type": "string", "format": "date" },
+            ],
+        }))
+        .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> PartialDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn missing_parts_are_start_of_period() {
+        assert_eq!(date("1850"), date("1850-01-01"));
+        assert_eq!(date("1850-03"), date("1850-03-01"));
+        assert!(date("1850") < date("1850-02"));
+        assert!(date("1850-12-31") < date("1851"));
+        assert!(date("1850-03") < date("1850-03-02"));
+    }
+
+    #[test]
+    fn negative_years() {
+        assert_eq!(date("-44").year, -44);
+        assert_eq!(date(&date("-44-03-15").to_string()), date("-44-03-15"));
+        assert!(date("-44") < date("-1"));
+        assert!(date("-1-12-31") < date("0"));
+        assert!("--44".parse::<PartialDate>().is_err());
+    }
+
+    #[test]
+    fn invalid_dates() {
+        for s in ["", "1850-13", "1850-03-32", "1850-3-1-1", "18a0", "1850-"] {
+            assert!(s.parse::<PartialDate>().is_err(), "{s}");
+        }
+    }
+}
@@ -0,0 +1,499 @@
+use std::cmp::Ordering;
+
+use geojson::JsonObject;
+use wax::{Glob, Pattern};
+
+use crate::{
+    date::PartialDate,
+    types::{CountryConfig, CountrySelector, PropertiesConfig},
+};
+
+/// Boolean expression over tag globs, e.g. `europe & !(historical | fictional-*)`.
+/// Glob matches if any tag of a country matches it
+#[derive(Debug)]
+pub enum TagExpr {
+    Glob(Glob<'static>),
+    Not(Box<TagExpr>),
+    And(Box<TagExpr>, Box<TagExpr>),
+    Or(Box<TagExpr>, Box<TagExpr>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Glob(String),
+    Not,
+    And,
+    Or,
+    Open,
+    Close,
+}
+
+impl TagExpr {
+    pub fn parse(expression: &str) -> TagExpr {
+        let tokens = tokenize(expression);
+        let mut pos = 0;
+
+        let expr = parse_or(&tokens, &mut pos, expression);
+
+        if pos != tokens.len() {
+            panic!(
+                "Invalid tag expression {expression}: unexpected {:?}",
+                tokens[pos]
+            );
+        }
+
+        expr
+    }
+
+    pub fn is_match(&self, tags: &[String]) -> bool {
+        match self {
+            TagExpr::Glob(glob) => tags.iter().any(|tag| glob.is_match(tag.as_str())),
+            TagExpr::Not(expr) => !expr.is_match(tags),
+            TagExpr::And(a, b) => a.is_match(tags) && b.is_match(tags),
+            TagExpr::Or(a, b) => a.is_match(tags) || b.is_match(tags),
+        }
+    }
+}
+
+fn tokenize(expression: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut glob = String::new();
+    // Operators inside of glob brackets (e.g. `[!2]`, `{a,b}`) are a part of the glob
+    let mut depth = 0;
+
+    for c in expression.chars() {
+        if depth > 0 {
+            match c {
+                '[' | '{' | '<' => depth += 1,
+                ']' | '}' | '>' => depth -= 1,
+                _ => {}
+            }
+            glob.push(c);
+            continue;
+        }
+
+        let token = match c {
+            '!' => Some(Token::Not),
+            '&' => Some(Token::And),
+            '|' => Some(Token::Or),
+            '(' => Some(Token::Open),
+            ')' => Some(Token::Close),
+            c if c.is_whitespace() => None,
+            c => {
+                if matches!(c, '[' | '{' | '<') {
+                    depth += 1;
+                }
+                glob.push(c);
+                continue;
+            }
+        };
+
+        if !glob.is_empty() {
+            tokens.push(Token::Glob(std::mem::take(&mut glob)));
+        }
+        tokens.extend(token);
+    }
+
+    if !glob.is_empty() {
+        tokens.push(Token::Glob(glob));
+    }
+
+    tokens
+}
+
+fn parse_or(tokens: &[Token], pos: &mut usize, expression: &str) -> TagExpr {
+    let mut expr = parse_and(tokens, pos, expression);
+
+    while tokens.get(*pos) == Some(&Token::Or) {
+        *pos += 1;
+        expr = TagExpr::Or(Box::new(expr), Box::new(parse_and(tokens, pos, expression)));
+    }
+
+    expr
+}
+
+fn parse_and(tokens: &[Token], pos: &mut usize, expression: &str) -> TagExpr {
+    let mut expr = parse_unary(tokens, pos, expression);
+
+    while tokens.get(*pos) == Some(&Token::And) {
+        *pos += 1;
+        expr = TagExpr::And(
+            Box::new(expr),
+            Box::new(parse_unary(tokens, pos, expression)),
+        );
+    }
+
+    expr
+}
+
+fn parse_unary(tokens: &[Token], pos: &mut usize, expression: &str) -> TagExpr {
+    let token = tokens.get(*pos);
+    *pos += 1;
+
+    match token {
+        Some(Token::Not) => TagExpr::Not(Box::new(parse_unary(tokens, pos, expression))),
+        Some(Token::Open) => {
+            let expr = parse_or(tokens, pos, expression);
+
+            if tokens.get(*pos) != Some(&Token::Close) {
+                panic!("Invalid tag expression {expression}: missing closing parenthesis");
+            }
+            *pos += 1;
+
+            expr
+        }
+        Some(Token::Glob(glob)) => TagExpr::Glob(new_glob(glob)),
+        Some(token) => panic!("Invalid tag expression {expression}: unexpected {token:?}"),
+        None => panic!("Invalid tag expression {expression}: unexpected end"),
+    }
+}
+
+fn new_glob(glob: &str) -> Glob<'static> {
+    match Glob::new(glob) {
+        Ok(glob) => glob.into_owned(),
+        Err(err) => panic!("Invalid glob {glob}: {err}"),
+    }
+}
+
+/// Condition on a country property, e.g. `foundation_date < 1900` or `government == monarchy`.
+/// A single field name checks that the field is set
+#[derive(Debug)]
+pub struct Condition {
+    field: String,
+    op: Option<(Op, Scalar)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Comparable value of a property
+#[derive(Debug, Clone, PartialEq)]
+enum Scalar {
+    Bool(bool),
+    Number(f64),
+    Date(PartialDate),
+    Text(String),
+}
+
+impl Scalar {
+    fn parse(s: &str) -> Scalar {
+        let s = s.trim();
+
+        if let Some(text) = s
+            .strip_prefix('"')
+            .and_then(|s| s.strip_suffix('"'))
+            .or_else(|| s.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')))
+        {
+            return Scalar::Text(text.to_owned());
+        }
+
+        match s {
+            "true" => Scalar::Bool(true),
+            "false" => Scalar::Bool(false),
+            s => {
+                if let Ok(n) = s.parse::<f64>() {
+                    Scalar::Number(n)
+                } else if let Ok(d) = s.parse::<PartialDate>() {
+                    Scalar::Date(d)
+                } else {
+                    Scalar::Text(s.to_owned())
+                }
+            }
+        }
+    }
+
+    fn from_json(value: &serde_json::Value) -> Option<Scalar> {
+        match value {
+            serde_json::Value::Bool(b) => Some(Scalar::Bool(*b)),
+            serde_json::Value::Number(n) => n.as_f64().map(Scalar::Number),
+            serde_json::Value::String(s) => Some(Scalar::parse(s)),
+            _ => None,
+        }
+    }
+
+    fn compare(&self, other: &Scalar) -> Option<Ordering> {
+        match (self, other) {
+            (Scalar::Bool(a), Scalar::Bool(b)) => Some(a.cmp(b)),
+            (Scalar::Number(a), Scalar::Number(b)) => a.partial_cmp(b),
+            (Scalar::Date(a), Scalar::Date(b)) => Some(a.cmp(b)),
+            // Whole numbers are compared with dates as years
+            (Scalar::Date(a), Scalar::Number(b)) if b.fract() == 0.0 => {
+                Some(a.cmp(&year_to_date(*b)))
+            }
+            (Scalar::Number(a), Scalar::Date(b)) if a.fract() == 0.0 => {
+                Some(year_to_date(*a).cmp(b))
+            }
+            (Scalar::Text(a), Scalar::Text(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+fn year_to_date(year: f64) -> PartialDate {
+    PartialDate {
+        year: year as i32,
+        month: None,
+        day: None,
+    }
+}
+
+impl Condition {
+    pub fn parse(condition: &str) -> Condition {
+        const OPS: [(&str, Op); 6] = [
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ];
+
+        // The leftmost operator outside of quotes splits the field from the value
+        let mut quote = None;
+
+        for (i, c) in condition.char_indices() {
+            match (quote, c) {
+                (None, '"' | '\'') => quote = Some(c),
+                (Some(q), c) if q == c => quote = None,
+                (Some(_), _) => {}
+                (None, _) => {
+                    let rest = &condition[i..];
+
+                    if let Some((token, op)) = OPS.iter().find(|(token, _)| rest.starts_with(token))
+                    {
+                        return Condition {
+                            field: condition[..i].trim().to_owned(),
+                            op: Some((*op, Scalar::parse(&rest[token.len()..]))),
+                        };
+                    }
+                }
+            }
+        }
+
+        let field = condition.trim();
+        if field.is_empty() || field.contains(char::is_whitespace) {
+            panic!("Invalid condition {condition}, expected `<field> <op> <value>`");
+        }
+
+        Condition {
+            field: field.to_owned(),
+            op: None,
+        }
+    }
+
+    pub fn is_match(&self, properties: &serde_json::Value) -> bool {
+        let value = match properties.get(&self.field) {
+            Some(serde_json::Value::Null) | None => return false,
+            Some(value) => value,
+        };
+
+        let Some((op, expected)) = &self.op else {
+            return true;
+        };
+
+        let Some(ordering) = Scalar::from_json(value).and_then(|v| v.compare(expected)) else {
+            return matches!(op, Op::Ne);
+        };
+
+        match op {
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// Compiled [`CountrySelector`]
+#[derive(Debug)]
+pub struct CountryFilter {
+    tags: Option<Vec<TagExpr>>,
+    exclude_tags: Vec<TagExpr>,
+    countries: Option<Vec<Glob<'static>>>,
+    exclude_countries: Vec<Glob<'static>>,
+    include_untagged: Option<bool>,
+    conditions: Vec<Condition>,
+}
+
+impl CountryFilter {
+    pub fn new(selector: &CountrySelector) -> CountryFilter {
+        let exprs = |exprs: &Option<Vec<String>>| {
+            exprs
+                .as_ref()
+                .map(|exprs| exprs.iter().map(|e| TagExpr::parse(e)).collect::<Vec<_>>())
+        };
+        let globs = |globs: &Option<Vec<String>>| {
+            globs
+                .as_ref()
+                .map(|globs| globs.iter().map(|g| new_glob(g)).collect::<Vec<_>>())
+        };
+
+        CountryFilter {
+            tags: exprs(&selector.tags).filter(|tags| !tags.is_empty()),
+            exclude_tags: exprs(&selector.exclude_tags).unwrap_or_default(),
+            countries: globs(&selector.countries),
+            exclude_countries: globs(&selector.exclude_countries).unwrap_or_default(),
+            include_untagged: selector.include_untagged,
+            conditions: selector
+                .conditions
+                .iter()
+                .flatten()
+                .map(|c| Condition::parse(c))
+                .collect(),
+        }
+    }
+
+    pub fn is_match(&self, id: &str, config: &CountryConfig) -> bool {
+        let tags = config.tags.as_deref().unwrap_or_default();
+        let untagged = tags.is_empty();
+
+        if self.exclude_countries.iter().any(|g| g.is_match(id))
+            || self.exclude_tags.iter().any(|e| e.is_match(tags))
+        {
+            return false;
+        }
+
+        if !self.conditions.is_empty() {
+            let properties = serde_json::to_value(config).unwrap();
+
+            if !self.conditions.iter().all(|c| c.is_match(&properties)) {
+                return false;
+            }
+        }
+
+        let by_id = self
+            .countries
+            .as_ref()
+            .map(|globs| globs.iter().any(|g| g.is_match(id)));
+
+        let by_tags = match &self.tags {
+            Some(_) if untagged => Some(self.include_untagged.unwrap_or(true)),
+            Some(exprs) => Some(exprs.iter().any(|e| e.is_match(tags))),
+            None if untagged && self.include_untagged == Some(false) => Some(false),
+            None => None,
+        };
+
+        match (by_id, by_tags) {
+            (None, None) => true,
+            (Some(matches), None) | (None, Some(matches)) => matches,
+            (Some(a), Some(b)) => a || b,
+        }
+    }
+}
+
+/// Compiled [`PropertiesConfig`]
+#[derive(Debug, Default)]
+pub struct PropertiesFilter {
+    allow: Option<Vec<Glob<'static>>>,
+    deny: Vec<Glob<'static>>,
+}
+
+impl PropertiesFilter {
+    pub fn new(config: &PropertiesConfig) -> PropertiesFilter {
+        PropertiesFilter {
+            allow: config
+                .allow
+                .as_ref()
+                .map(|allow| allow.iter().map(|g| new_glob(g)).collect()),
+            deny: config.deny.iter().flatten().map(|g| new_glob(g)).collect(),
+        }
+    }
+
+    /// Removes properties which are not allowed or are denied
+    pub fn filter(&self, properties: &mut JsonObject) {
+        properties.retain(|key, _| {
+            let allowed = match &self.allow {
+                Some(allow) => allow.iter().any(|g| g.is_match(key.as_str())),
+                None => true,
+            };
+
+            allowed && !self.deny.iter().any(|g| g.is_match(key.as_str()))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(expression: &str, tags: &[&str]) -> bool {
+        let tags: Vec<String> = tags.iter().map(|t| t.to_string()).collect();
+        TagExpr::parse(expression).is_match(&tags)
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert!(matches("a | b & c", &["a"]));
+        assert!(matches("a | b & c", &["b", "c"]));
+        assert!(!matches("a | b & c", &["b"]));
+        assert!(!matches("a & b | c & d", &["a", "d"]));
+    }
+
+    #[test]
+    fn not_and_parentheses() {
+        assert!(matches("!a", &["b"]));
+        assert!(!matches("!a", &["a"]));
+        assert!(!matches("(a | b) & c", &["a"]));
+        assert!(matches("(a | b) & c", &["b", "c"]));
+        assert!(matches("europe & !(historical | fictional-*)", &["europe"]));
+        assert!(!matches(
+            "europe & !(historical | fictional-*)",
+            &["europe", "fictional-1"]
+        ));
+        assert!(matches("!!a", &["a"]));
+    }
+
+    #[test]
+    fn operators_inside_glob_brackets() {
+        assert!(matches("v[!2]", &["v1"]));
+        assert!(!matches("v[!2]", &["v2"]));
+        assert!(matches("{a,b}-x | c", &["b-x"]));
+    }
+
+    #[test]
+    #[should_panic(expected = "missing closing parenthesis")]
+    fn unclosed_parenthesis() {
+        TagExpr::parse("(a | b");
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected Close")]
+    fn unopened_parenthesis() {
+        TagExpr::parse("a | b)");
+    }
+
+    fn condition(condition: &str, properties: serde_json::Value) -> bool {
+        Condition::parse(condition).is_match(&properties)
+    }
+
+    #[test]
+    fn dates_compared_with_numbers_as_years() {
+        let country = serde_json::json!({ "foundation_date": "1850-03" });
+
+        assert!(condition("foundation_date < 1900", country.clone()));
+        assert!(condition("foundation_date >= 1850", country.clone()));
+        assert!(!condition("foundation_date < 1850", country.clone()));
+        assert!(condition("foundation_date > 1850-01-15", country));
+    }
+
+    #[test]
+    fn leftmost_operator_outside_quotes() {
+        let country = serde_json::json!({ "name": "a==b", "population": 10 });
+
+        assert!(!condition(r#"name != "a==b""#, country.clone()));
+        assert!(condition("name == 'a==b'", country.clone()));
+        assert!(condition("population <= 10", country.clone()));
+        assert!(!condition("population < 10", country.clone()));
+        assert!(condition("population", country.clone()));
+        assert!(!condition("capital", country));
+    }
+}
@@ -1,13 +1,20 @@
-use std::{fs, path::Path};
+use std::{fs, path::PathBuf};
 
-pub fn init(name: String) {
+pub fn init(name: Option<String>, project_dir: Option<PathBuf>) {
     let config = include_str!("./templates/config.toml");
     let country_config = include_str!("./templates/country.toml");
     let geojson = include_str!("./templates/sample.geojson");
 
-    let root_folder = Path::new(&name);
-    let country_folder = Path::new(&name).join("countries").join("sample_country_id");
-    let nature_folder = Path::new(&name).join("nature");
+    let root_folder = match (name, project_dir) {
+        (Some(name), Some(dir)) => panic!(
+            "Project folder is ambiguous, got name {name} and --project-dir {}",
+            dir.display()
+        ),
+        (None, Some(dir)) => dir,
+        (name, None) => PathBuf::from(name.unwrap_or_else(|| "map".to_owned())),
+    };
+    let country_folder = root_folder.join("countries").join("sample_country_id");
+    let nature_folder = root_folder.join("nature");
 
     fs::create_dir_all(&country_folder).unwrap();
     fs::create_dir_all(&nature_folder).unwrap();
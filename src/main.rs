@@ -1,20 +1,30 @@
 use clap::Parser;
 
 mod build;
+mod check;
+mod date;
+mod filter;
 mod init;
 mod new;
+mod report;
+mod schema;
 mod types;
 mod utils;
 
-use types::Commands;
+use types::{Commands, ProjectPaths};
 
 fn main() {
     let args = types::Cli::parse();
+    let paths = ProjectPaths::new(args.project_dir.clone(), args.config);
 
     match args.cmd {
-        Commands::Build => build::build(),
-        Commands::Init { name } => init::init(name),
-        Commands::New { cmd } => new::new(cmd),
-        _ => unimplemented!(),
+        Commands::Build(build_args) => build::build(&paths, build_args),
+        Commands::Check {
+            show_config,
+            sliver_area,
+        } => check::check(&paths, show_config, sliver_area),
+        Commands::Init { name } => init::init(name, args.project_dir),
+        Commands::New { cmd } => new::new(cmd, &paths),
+        Commands::Schema { file, output } => schema::schema(file, output),
     }
 }
@@ -1,26 +1,28 @@
-use std::{fs, path::Path};
+use std::fs;
 use toml_edit::{value, DocumentMut, Value};
 
 use crate::{
-    types::{CountryConfig, NewCommands},
+    types::{CountryConfig, LineConfig, LineKind, NewCommands, NewLineArgs, ProjectPaths},
     utils::{hash_hex_color, read_config},
 };
 
-pub fn new(cmd: NewCommands) {
+pub fn new(cmd: NewCommands, paths: &ProjectPaths) {
     match cmd {
         NewCommands::Country {
             name,
             id,
             description,
             foundation_date,
+            dissolution_date,
             flag,
             about,
             fill,
             stroke,
+            parent,
         } => {
             let name = name.unwrap_or_default();
             let description = description.unwrap_or_default();
-            let foundation_date = foundation_date.unwrap_or_default();
+            let foundation_date = foundation_date.map(|d| d.to_string()).unwrap_or_default();
             let flag = flag.unwrap_or_default();
             let fill = fill.unwrap_or_else(|| hash_hex_color(id.clone() + "_fill"));
             let stroke = stroke.unwrap_or_else(|| hash_hex_color(id.clone() + "_stroke"));
@@ -29,17 +31,20 @@ pub fn new(cmd: NewCommands) {
                 name: name.clone(),
                 description,
                 foundation_date,
+                dissolution_date,
                 flag,
                 about,
                 fill,
                 stroke,
                 tags: None,
+                parent,
+                extra: toml::Table::new(),
             };
 
-            let config = fs::read_to_string("config.toml").unwrap();
+            let config = fs::read_to_string(&paths.config).unwrap();
 
             // Validate config
-            read_config();
+            read_config(paths);
 
             // Get actual config
             let mut config = config.parse::<DocumentMut>().unwrap();
@@ -56,10 +61,10 @@ pub fn new(cmd: NewCommands) {
 
             config["main"]["layers"] = value(layers);
 
-            fs::write("config.toml", config.to_string()).unwrap();
+            fs::write(&paths.config, config.to_string()).unwrap();
 
             // Add country to countries
-            let country_folder = Path::new(".").join("countries").join(&id);
+            let country_folder = paths.country_folder(&id);
             fs::create_dir_all(&country_folder).unwrap();
             fs::write(
                 country_folder.join("country.toml"),
@@ -72,5 +77,33 @@ pub fn new(cmd: NewCommands) {
             )
             .unwrap();
         }
+        NewCommands::Road(args) => new_line(LineKind::Road, args, paths),
+        NewCommands::River(args) => new_line(LineKind::River, args, paths),
+        NewCommands::Railway(args) => new_line(LineKind::Railway, args, paths),
     }
 }
+
+fn new_line(kind: LineKind, args: NewLineArgs, paths: &ProjectPaths) {
+    let line = LineConfig {
+        name: args.name.unwrap_or_default(),
+        description: args.description,
+        class: args.class,
+        stroke: args.stroke,
+        width: None,
+        min_zoom: None,
+        extra: toml::Table::new(),
+    };
+
+    let line_folder = paths.line_folder(kind, &args.id);
+    fs::create_dir_all(&line_folder).unwrap();
+    fs::write(
+        line_folder.join("line.toml"),
+        toml::to_string_pretty(&line).unwrap(),
+    )
+    .unwrap();
+    fs::write(
+        line_folder.join("line.geojson"),
+        include_str!("./templates/sample.geojson"),
+    )
+    .unwrap();
+}
@@ -0,0 +1,263 @@
+use std::{fs, path::Path, time::Duration};
+
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{
+    date::PartialDate,
+    types::Compression,
+    utils::{compress, hash_hex},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, Default)]
+pub enum LogFormat {
+    /// Human readable log
+    #[default]
+    Text,
+    /// One json object per line
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Dissolve,
+    Diff,
+    Generate,
+}
+
+impl Stage {
+    fn to_past(self) -> &'static str {
+        match self {
+            Stage::Dissolve => "Dissolved",
+            Stage::Diff => "Diffed",
+            Stage::Generate => "Generated files",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct BuildReport {
+    pub total_ms: f64,
+    pub items: Vec<ProcessingReport>,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct ProcessingReport {
+    pub name: Option<String>,
+    pub output_folder: String,
+    pub dissolve_ms: f64,
+    pub diff_ms: f64,
+    pub generate_ms: f64,
+    pub total_ms: f64,
+    pub countries: Vec<CountryReport>,
+    pub files: Vec<FileReport>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CountryReport {
+    pub id: String,
+    /// Date of the processed snapshot or series step
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date: Option<PartialDate>,
+    /// Vertices of dissolved land before diffing
+    pub vertices_before: usize,
+    /// Vertices of the final land
+    pub vertices_after: usize,
+    /// Area of land outside of the land mask in square degrees
+    pub trimmed_area: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileReport {
+    pub path: String,
+    pub size: usize,
+    /// xxh3 hash of contents in hex
+    pub hash: String,
+}
+
+/// Logs build progress and collects it into a [`BuildReport`]
+pub struct Reporter {
+    format: LogFormat,
+    /// Date of the current series step, prefixed to warnings
+    step: Option<PartialDate>,
+    pub report: BuildReport,
+}
+
+impl Reporter {
+    pub fn new(format: LogFormat) -> Reporter {
+        Reporter {
+            format,
+            step: None,
+            report: BuildReport::default(),
+        }
+    }
+
+    fn item(&mut self) -> &mut ProcessingReport {
+        self.report
+            .items
+            .last_mut()
+            .expect("No processing item started")
+    }
+
+    fn log_json(&self, value: serde_json::Value) {
+        println!("{value}");
+    }
+
+    pub fn start_item(&mut self, name: Option<String>, output_folder: String) {
+        match self.format {
+            LogFormat::Text => println!("--- {} ---", output_folder),
+            LogFormat::Json => self.log_json(json!({
+                "event": "start",
+                "name": name,
+                "output_folder": output_folder,
+            })),
+        }
+
+        self.step = None;
+        self.report.items.push(ProcessingReport {
+            name,
+            output_folder,
+            ..Default::default()
+        });
+    }
+
+    /// Starts a step of a time-series item
+    pub fn start_step(&mut self, date: PartialDate) {
+        match self.format {
+            LogFormat::Text => println!("- {date} -"),
+            LogFormat::Json => self.log_json(json!({
+                "event": "step",
+                "date": date,
+            })),
+        }
+
+        self.step = Some(date);
+    }
+
+    pub fn timing(&mut self, stage: Stage, elapsed: Duration) {
+        match self.format {
+            LogFormat::Text => println!("{} in {:?}", stage.to_past(), elapsed),
+            LogFormat::Json => self.log_json(json!({
+                "event": "timing",
+                "stage": stage,
+                "ms": to_ms(elapsed),
+            })),
+        }
+
+        let item = self.item();
+        let ms = match stage {
+            Stage::Dissolve => &mut item.dissolve_ms,
+            Stage::Diff => &mut item.diff_ms,
+            Stage::Generate => &mut item.generate_ms,
+        };
+        // Stages of time-series builds run once per step
+        *ms += to_ms(elapsed);
+    }
+
+    pub fn warning(&mut self, message: String) {
+        let message = match self.step {
+            Some(date) => format!("{date}: {message}"),
+            None => message,
+        };
+
+        match self.format {
+            LogFormat::Text => println!("Warning: {message}"),
+            LogFormat::Json => self.log_json(json!({
+                "event": "warning",
+                "message": message,
+            })),
+        }
+
+        self.item().warnings.push(message);
+    }
+
+    pub fn country(&mut self, country: CountryReport) {
+        if country.trimmed_area > 0.0 {
+            match self.format {
+                LogFormat::Text => println!(
+                    "Trimmed {:.6} sq. degrees of land of country {} outside of the land mask",
+                    country.trimmed_area, country.id
+                ),
+                LogFormat::Json => self.log_json(json!({
+                    "event": "trimmed",
+                    "country": country.id,
+                    "area": country.trimmed_area,
+                })),
+            }
+        }
+
+        self.item().countries.push(country);
+    }
+
+    /// Writes a file with its compressed copies and records their sizes and hashes
+    pub fn write_file(
+        &mut self,
+        path: &Path,
+        contents: impl AsRef<[u8]>,
+        compressions: &[Compression],
+    ) {
+        let contents = contents.as_ref();
+
+        for compression in compressions {
+            let mut compressed_path = path.as_os_str().to_owned();
+            compressed_path.push(".");
+            compressed_path.push(compression.extension());
+
+            self.write_file(
+                Path::new(&compressed_path),
+                compress(contents, *compression),
+                &[],
+            );
+        }
+
+        fs::write(path, contents).unwrap();
+
+        self.item().files.push(FileReport {
+            path: path.to_string_lossy().to_string(),
+            size: contents.len(),
+            hash: hash_hex(contents),
+        });
+    }
+
+    pub fn finish_item(&mut self, elapsed: Duration) {
+        let item = self.item();
+        item.total_ms = to_ms(elapsed);
+        let output_folder = item.output_folder.clone();
+
+        match self.format {
+            LogFormat::Text => {
+                let processed = format!("{:?}", elapsed);
+
+                println!(
+                    "--- {} {}---\n",
+                    processed,
+                    "-".repeat(output_folder.len().saturating_sub(processed.len()))
+                );
+            }
+            LogFormat::Json => self.log_json(json!({
+                "event": "finish",
+                "output_folder": output_folder,
+                "ms": to_ms(elapsed),
+            })),
+        }
+    }
+
+    pub fn finish(&mut self, elapsed: Duration) {
+        self.report.total_ms = to_ms(elapsed);
+
+        match self.format {
+            LogFormat::Text => println!("Total time: {:?}", elapsed),
+            LogFormat::Json => self.log_json(json!({
+                "event": "done",
+                "ms": to_ms(elapsed),
+            })),
+        }
+    }
+}
+
+fn to_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
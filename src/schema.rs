@@ -0,0 +1,50 @@
+use std::{fs, path::Path};
+
+use schemars::{schema::RootSchema, schema_for};
+
+use crate::types::{Config, CountryConfig, LineConfig, RegionConfig, SchemaFile};
+
+pub fn schema(file: SchemaFile, output: Option<String>) {
+    match output {
+        Some(output) => {
+            let out_folder = Path::new(&output);
+            fs::create_dir_all(out_folder).unwrap();
+
+            for file in [
+                SchemaFile::Config,
+                SchemaFile::Country,
+                SchemaFile::Region,
+                SchemaFile::Line,
+            ] {
+                fs::write(
+                    out_folder.join(schema_file_name(file)),
+                    schema_to_string(get_schema(file)),
+                )
+                .unwrap();
+            }
+        }
+        None => println!("{}", schema_to_string(get_schema(file))),
+    }
+}
+
+pub fn get_schema(file: SchemaFile) -> RootSchema {
+    match file {
+        SchemaFile::Config => schema_for!(Config),
+        SchemaFile::Country => schema_for!(CountryConfig),
+        SchemaFile::Region => schema_for!(RegionConfig),
+        SchemaFile::Line => schema_for!(LineConfig),
+    }
+}
+
+fn schema_file_name(file: SchemaFile) -> &'static str {
+    match file {
+        SchemaFile::Config => "config.schema.json",
+        SchemaFile::Country => "country.schema.json",
+        SchemaFile::Region => "region.schema.json",
+        SchemaFile::Line => "line.schema.json",
+    }
+}
+
+fn schema_to_string(schema: RootSchema) -> String {
+    serde_json::to_string_pretty(&schema).unwrap()
+}
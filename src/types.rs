@@ -1,27 +1,94 @@
-use clap::{Parser, Subcommand};
+use std::{
+    collections::BTreeMap,
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use geo::{Geometry, MultiPolygon};
 use geo::{Point, Polygon};
-use geojson::{FeatureCollection, Value};
+use geojson::{FeatureCollection, JsonObject, Value};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
+use crate::{date::PartialDate, report::LogFormat};
+
 #[derive(Debug, Parser)]
 #[command(name = "cimengine", bin_name = "cimengine")]
 #[command(about = "CIMEngine build tools")]
 pub struct Cli {
     #[command(subcommand)]
     pub cmd: Commands,
+
+    /// Project folder. Defaults to the folder of config file or the current folder
+    #[clap(long, global = true)]
+    pub project_dir: Option<PathBuf>,
+    /// Project config file. Defaults to config.toml in project folder
+    #[clap(long, global = true)]
+    pub config: Option<PathBuf>,
+}
+
+/// Locations of project files
+#[derive(Debug, Clone)]
+pub struct ProjectPaths {
+    pub dir: PathBuf,
+    pub config: PathBuf,
+}
+
+impl ProjectPaths {
+    pub fn new(project_dir: Option<PathBuf>, config: Option<PathBuf>) -> ProjectPaths {
+        let dir = match (project_dir, &config) {
+            (Some(dir), _) => dir,
+            (None, Some(config)) => config.parent().map(|p| p.to_path_buf()).unwrap_or_default(),
+            (None, None) => PathBuf::from("."),
+        };
+        let config = config.unwrap_or_else(|| dir.join("config.toml"));
+
+        ProjectPaths { dir, config }
+    }
+
+    pub fn country_folder(&self, id: &str) -> PathBuf {
+        self.dir.join("countries").join(id)
+    }
+
+    pub fn line_folder(&self, kind: LineKind, id: &str) -> PathBuf {
+        self.dir.join(kind.folder()).join(id)
+    }
+
+    /// Resolves a path from config relative to project folder
+    pub fn resolve(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.dir.join(path).components().collect()
+    }
 }
 
 #[derive(Debug, Subcommand)]
 #[clap(author, version, about)]
 pub enum Commands {
     /// Build project
-    Build,
-    /// Initialize a new project
+    Build(BuildArgs),
+    /// Check project for errors without writing any files
+    Check {
+        /// Print config with resolved processing items
+        #[clap(long)]
+        show_config: bool,
+        /// Warn about unclaimed pieces of land between countries smaller than this area in
+        /// square degrees, they are probably drawing mistakes
+        #[clap(long, default_value_t = 0.01)]
+        sliver_area: f64,
+    },
+    /// Print JSON Schema of project files, for editors with TOML schema support
+    Schema {
+        #[clap(value_enum, default_value = "config")]
+        file: SchemaFile,
+        /// Write schemas of all project files to this folder instead of printing
+        #[clap(long)]
+        output: Option<String>,
+    },
+    /// Initialize a new project in folder `name` or `--project-dir`
     Init {
-        #[clap(default_value = "map")]
-        name: String,
+        /// Defaults to "map" without `--project-dir`
+        name: Option<String>,
     },
     /// Utility for creating countries, roads, etc.
     New {
@@ -30,6 +97,37 @@ pub enum Commands {
     },
 }
 
+#[derive(Debug, Args)]
+pub struct BuildArgs {
+    /// Build only processing items with this name or output folder
+    #[clap(long = "output")]
+    pub outputs: Vec<String>,
+    /// Build only these countries. Their neighbors are still loaded to diff borders correctly.
+    /// Output files contain only these countries and are written into `<output_folder>/partial`
+    #[clap(long = "country")]
+    pub countries: Vec<String>,
+    /// Build snapshots of all processing items at this date (YYYY, YYYY-MM or YYYY-MM-DD)
+    #[clap(long)]
+    pub at: Option<PartialDate>,
+    /// Write machine-readable build report to this file
+    #[clap(long)]
+    pub report: Option<PathBuf>,
+    #[clap(long, value_enum, default_value = "text")]
+    pub format: LogFormat,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum SchemaFile {
+    /// config.toml
+    Config,
+    /// country.toml
+    Country,
+    /// region.toml
+    Region,
+    /// line.toml of roads, rivers and railways
+    Line,
+}
+
 #[derive(Debug, Subcommand)]
 pub enum NewCommands {
     /// Create new country
@@ -40,7 +138,9 @@ pub enum NewCommands {
         #[clap(long)]
         description: Option<String>,
         #[clap(long)]
-        foundation_date: Option<String>,
+        foundation_date: Option<PartialDate>,
+        #[clap(long)]
+        dissolution_date: Option<PartialDate>,
         #[clap(long)]
         flag: Option<String>,
         #[clap(long)]
@@ -49,49 +149,369 @@ pub enum NewCommands {
         fill: Option<String>,
         #[clap(long)]
         stroke: Option<String>,
+        /// Id of the country this one is a member of
+        #[clap(long)]
+        parent: Option<String>,
     },
+    /// Create new road
+    Road(NewLineArgs),
+    /// Create new river
+    River(NewLineArgs),
+    /// Create new railway
+    Railway(NewLineArgs),
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Args)]
+pub struct NewLineArgs {
+    pub id: String,
+    #[clap(long)]
+    pub name: Option<String>,
+    #[clap(long)]
+    pub description: Option<String>,
+    #[clap(long)]
+    pub class: Option<String>,
+    #[clap(long)]
+    pub stroke: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
 pub struct Config {
     pub main: MainConfig,
+    /// Declarations of custom country fields
+    pub fields: Option<BTreeMap<String, FieldConfig>>,
+    pub markers: Option<MarkersConfig>,
+    pub properties: Option<PropertiesConfig>,
+    /// Clip land of countries to land of the world before diffing. Without it land of the
+    /// world for `show_unclaimed` is the union of `nature/*.geojson` except `water.geojson`
+    pub land_mask: Option<LandMaskConfig>,
+    /// Options inherited by every processing item which doesn't `extends` another one
+    #[schemars(with = "Option<BTreeMap<String, serde_json::Value>>")]
+    pub processing_defaults: Option<toml::Table>,
     pub processing: Vec<ProcessingConfig>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Land of the world, relative to project folder. With both options land of `path` without
+/// `water` is used. With only `water` land is the bounding box of water without it
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct LandMaskConfig {
+    /// Geojson file with land polygons
+    pub path: Option<String>,
+    /// Geojson file with water polygons, land is everything else, e.g. `nature/water.geojson`
+    pub water: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
 pub struct MainConfig {
+    /// Ids of countries. Order matters when building countries, it affects the processing of area
+    /// intersections
     pub layers: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default)]
+pub struct PropertiesConfig {
+    /// Globs of geojson properties passed through to the output. All properties by default
+    pub allow: Option<Vec<String>>,
+    /// Globs of geojson properties never passed through to the output
+    pub deny: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default)]
+pub struct MarkersConfig {
+    /// How to report markers that lie outside of their country's land
+    #[serde(default)]
+    pub validation: MarkerValidation,
+    /// What to do with markers that lie outside of their country's land
+    #[serde(default)]
+    pub outside: MarkerOutsideAction,
+    /// Additional marker types. Types with the same id as built-in ones replace them
+    pub types: Option<Vec<MarkerType>>,
+}
+
+impl MarkersConfig {
+    /// Built-in marker types merged with types declared in config
+    pub fn marker_types(&self) -> Vec<MarkerType> {
+        let mut types = MarkerType::builtin();
+
+        for ty in self.types.clone().unwrap_or_default() {
+            match types.iter_mut().find(|t| t.id == ty.id) {
+                Some(t) => *t = ty,
+                None => types.push(ty),
+            }
+        }
+
+        types
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MarkerValidation {
+    Ignore,
+    #[default]
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MarkerOutsideAction {
+    /// Leave the marker as is
+    #[default]
+    Keep,
+    /// Remove the marker from the output
+    Drop,
+    /// Move the marker to the closest point of the country's land
+    Clip,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
 pub struct ProcessingConfig {
+    /// Name used to select this item with `cimengine build --output`
+    pub name: Option<String>,
+    /// Include only countries existing at this date and use their geometry valid at it.
+    /// Overridden by `cimengine build --at`
+    pub date: Option<PartialDate>,
+    /// Write `timeline.json` with foundation, dissolution and border change events of countries
+    pub timeline: Option<bool>,
+    /// Build the map at each of these dates into `<output_folder>/<date>`, skipping dates
+    /// where nothing changed. Ignored with `cimengine build --at`
+    pub series: Option<DateSeries>,
+    /// Name of processing item to inherit options from. Tables are merged, `countries_rewrite`
+    /// and other lists of tables are concatenated, other options are replaced. An empty list
+    /// clears inherited tables
+    pub extends: Option<String>,
     pub show_markers: Option<bool>,
+    /// Include regions of countries. Defaults to true
+    pub show_regions: Option<bool>,
+    /// Include roads, rivers and railways. Defaults to true
+    pub show_lines: Option<bool>,
+    /// Include land not covered by any country as `type: "unclaimed"` features, see
+    /// `land_mask`. Defaults to false
+    pub show_unclaimed: Option<bool>,
+    /// Level of country hierarchy to render, 0 is top-level countries. Members of countries at
+    /// this level are dissolved into their land
+    pub hierarchy_level: Option<usize>,
     pub output_folder: String,
+    /// Add content hash to names of generated files, e.g. `geo.3fa2c1d0.geojson`. `public.json`
+    /// keeps its name, file names of its `geo` and `countries` urls are replaced with hashed ones.
+    /// See `manifest.json` for actual names. Files of the previous build are removed
+    pub hashed_filenames: Option<bool>,
+    /// Write compressed copies of every generated file next to it
+    pub compress: Option<Vec<Compression>>,
+    /// Formatting of all generated json files. By default `geo.geojson` and `public.json` are
+    /// minified and other files are pretty
+    pub json_format: Option<JsonFormat>,
+    /// Sort object keys and end files with a newline, so generated files produce minimal diffs.
+    /// Implies pretty `json_format` unless set
+    pub canonical: Option<bool>,
 
-    pub tags: Option<Vec<String>>,
+    /// Countries included in this item
+    #[serde(flatten)]
+    pub selector: CountrySelector,
+    /// Rewrite properties of countries by tags
     pub countries_rewrite: Option<Vec<CountryRewriteConfig>>,
+    /// Rewrite land of countries
+    pub geometry_rewrite: Option<Vec<GeometryRewriteConfig>>,
+    /// Generate territorial waters of countries
+    pub waters: Option<WatersConfig>,
+    /// Information for public repository in cimengine. See: https://github.com/CIMEngine/MapList
     pub public: Option<PublicConfig>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct CountryRewriteConfig {
+impl ProcessingConfig {
+    /// Whether `name` is the name or the output folder of this item
+    pub fn is_match(&self, name: &str) -> bool {
+        self.name.as_deref() == Some(name) || self.output_folder == name
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct GeometryRewriteConfig {
+    /// Countries to rewrite
+    #[serde(flatten)]
+    pub selector: CountrySelector,
+    /// Replace land with territories from this geojson file, relative to project folder.
+    /// Applied before diffing
+    pub replace_land: Option<String>,
+    /// Grow (positive) or shrink (negative) land by this distance in degrees. Applied before
+    /// diffing
+    pub buffer: Option<f64>,
+    /// Merge land and markers of selected countries into the country with this id, other selected
+    /// countries are removed. Applied after diffing
+    pub merge_into: Option<String>,
+    /// Remove land but keep markers. Applied after diffing
+    pub hide_land: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct WatersConfig {
+    /// Width of waters from the coastline in degrees
+    pub distance: f64,
+    /// Geojson files with water polygons, relative to project folder. Waters are clipped to
+    /// them. Defaults to `nature/water.geojson` if it exists. Without water polygons waters are
+    /// everything outside of land
+    pub water_layers: Option<Vec<String>>,
+    /// Resolution of waters claimed by several countries
+    #[serde(default)]
+    pub overlap: WatersOverlap,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WatersOverlap {
+    /// Divide along the line of equal distance to the coasts of both countries (approximated)
+    #[default]
+    Split,
+    /// Give to the country which also wins overlapping land, see `layers`
+    Priority,
+}
+
+// Selection of countries. Without any fields all countries are selected
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default)]
+pub struct CountrySelector {
+    /// Boolean expressions over tag globs, e.g. `"europe & !historical"`. Operators: `&`, `|`,
+    /// `!` and parentheses. Country is selected if any expression matches
     pub tags: Option<Vec<String>>,
+    /// Tag expressions of countries which are never selected
+    pub exclude_tags: Option<Vec<String>>,
+    /// Globs of selected country ids. Countries are selected if they match either `tags` or
+    /// `countries`
+    pub countries: Option<Vec<String>>,
+    /// Globs of country ids which are never selected
+    pub exclude_countries: Option<Vec<String>>,
+    /// Whether countries without tags are selected. Defaults to true
+    pub include_untagged: Option<bool>,
+    /// Conditions on country properties which must all hold, e.g. `"foundation_date < 1900"`.
+    /// Operators: `==`, `!=`, `<`, `<=`, `>`, `>=`. A single field name checks that it is set
+    pub conditions: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct CountryRewriteConfig {
+    /// Countries to rewrite
+    #[serde(flatten)]
+    pub selector: CountrySelector,
     pub properties: CountryRewriteConfigProps,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
 pub struct CountryRewriteConfigProps {
     pub name: Option<String>,
     pub description: Option<String>,
     pub foundation_date: Option<String>,
+    pub dissolution_date: Option<PartialDate>,
     pub flag: Option<String>,
     pub fill: Option<String>,
     pub stroke: Option<String>,
     pub about: Option<String>,
+    pub parent: Option<String>,
+    /// Replace all tags
     pub tags: Option<Vec<String>>,
+    /// Tags added after replacing
+    pub add_tags: Option<Vec<String>>,
+    /// Globs of removed tags, applied before adding
+    pub remove_tags: Option<Vec<String>>,
+    /// Custom fields
+    #[serde(flatten)]
+    #[schemars(with = "BTreeMap<String, serde_json::Value>")]
+    pub extra: toml::Table,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JsonFormat {
+    Minified,
+    Pretty,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+pub enum Compression {
+    #[serde(rename = "gzip")]
+    Gzip,
+    #[serde(rename = "br")]
+    Brotli,
+}
+
+impl Compression {
+    pub const ALL: [Compression; 2] = [Compression::Gzip, Compression::Brotli];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Brotli => "br",
+        }
+    }
+}
+
+/// Dates of a time-series build: a list or a range with a step in years
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+#[serde(untagged)]
+pub enum DateSeries {
+    List(Vec<PartialDate>),
+    Range {
+        from: PartialDate,
+        to: PartialDate,
+        /// Years between dates
+        step: NonZeroU32,
+    },
+}
+
+impl DateSeries {
+    pub fn dates(&self) -> Vec<PartialDate> {
+        match self {
+            DateSeries::List(dates) => dates.clone(),
+            DateSeries::Range { from, to, step } => (0..)
+                .map(|i| PartialDate {
+                    year: from.year + i * step.get() as i32,
+                    ..*from
+                })
+                .take_while(|date| date <= to)
+                .collect(),
+        }
+    }
+}
+
+/// Step of `series.json`
+#[derive(Debug, Serialize)]
+pub struct SeriesStep {
+    pub date: PartialDate,
+    /// Folder with files of this step, the same as of the previous step if nothing changed
+    pub folder: String,
+}
+
+/// Event of `timeline.json`
+#[derive(Debug, Serialize, Clone)]
+pub struct TimelineEvent {
+    pub date: PartialDate,
+    pub country: String,
+    pub event: TimelineEventType,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelineEventType {
+    Founded,
+    Dissolved,
+    Borders,
+}
+
+/// List of generated files, written to `manifest.json`
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Manifest {
+    /// Generated files by their original name
+    pub files: BTreeMap<String, ManifestEntry>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ManifestEntry {
+    /// Actual file name
+    pub path: String,
+    /// xxh3 hash of contents in hex
+    pub hash: String,
+    pub size: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
 pub struct PublicConfig {
     pub name: String,
     pub description: String,
@@ -99,16 +519,175 @@ pub struct PublicConfig {
     pub countries: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
 pub struct CountryConfig {
     pub name: String,
     pub description: String,
+    /// Date in `YYYY`, `YYYY-MM` or `YYYY-MM-DD` format. Empty if unknown
     pub foundation_date: String,
+    /// Date since which the country doesn't exist
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dissolution_date: Option<PartialDate>,
     pub flag: String,
     pub fill: String,
     pub stroke: String,
     pub about: Option<String>,
     pub tags: Option<Vec<String>>,
+    /// Id of overlord, union or other country this one is a member of
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Custom fields, see `fields` in project config
+    #[serde(flatten, serialize_with = "serialize_toml_table")]
+    #[schemars(with = "BTreeMap<String, serde_json::Value>")]
+    pub extra: toml::Table,
+}
+
+impl CountryConfig {
+    pub fn foundation(&self) -> Result<Option<PartialDate>, String> {
+        if self.foundation_date.trim().is_empty() {
+            return Ok(None);
+        }
+
+        self.foundation_date.parse().map(Some)
+    }
+
+    /// Whether the country is founded and not dissolved at `date`
+    pub fn exists_at(&self, date: &PartialDate) -> Result<bool, String> {
+        let founded = self.foundation()?.is_none_or(|f| f <= *date);
+        let dissolved = self.dissolution_date.is_some_and(|d| d <= *date);
+
+        Ok(founded && !dissolved)
+    }
+}
+
+/// Subnational region, read from `countries/<country>/regions/<id>/region.toml`
+/// with geometry in `region.geojson`
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct RegionConfig {
+    pub name: String,
+    pub description: Option<String>,
+    /// Defaults to fill of the country
+    pub fill: Option<String>,
+    /// Defaults to stroke of the country
+    pub stroke: Option<String>,
+    /// Custom fields, passed through to countries.json
+    #[serde(flatten, serialize_with = "serialize_toml_table")]
+    #[schemars(with = "BTreeMap<String, serde_json::Value>")]
+    pub extra: toml::Table,
+}
+
+/// Kind of line layer, stored in `roads/`, `rivers/` or `railways/` of the project folder
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LineKind {
+    Road,
+    River,
+    Railway,
+}
+
+impl LineKind {
+    pub const ALL: [LineKind; 3] = [LineKind::Road, LineKind::River, LineKind::Railway];
+
+    pub fn folder(self) -> &'static str {
+        match self {
+            LineKind::Road => "roads",
+            LineKind::River => "rivers",
+            LineKind::Railway => "railways",
+        }
+    }
+
+    pub fn to_str(self) -> &'static str {
+        match self {
+            LineKind::Road => "road",
+            LineKind::River => "river",
+            LineKind::Railway => "railway",
+        }
+    }
+}
+
+/// Road, river or railway, read from `<kind folder>/<id>/line.toml` with geometry in
+/// `line.geojson`
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct LineConfig {
+    pub name: String,
+    pub description: Option<String>,
+    /// Kind specific class, e.g. `highway` for roads or `navigable` for rivers
+    pub class: Option<String>,
+    pub stroke: Option<String>,
+    pub width: Option<f64>,
+    pub min_zoom: Option<u8>,
+    /// Custom fields, passed through to the output
+    #[serde(flatten, serialize_with = "serialize_toml_table")]
+    #[schemars(with = "BTreeMap<String, serde_json::Value>")]
+    pub extra: toml::Table,
+}
+
+#[derive(Debug, Clone)]
+pub struct LineData {
+    pub id: String,
+    pub kind: LineKind,
+    pub config: LineConfig,
+    /// LineString and MultiLineString features
+    pub geo: FeatureCollection,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct FieldConfig {
+    pub r#type: FieldType,
+    pub required: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    Date,
+    Array,
+    Table,
+}
+
+impl FieldType {
+    pub fn is_match(&self, value: &toml::Value) -> bool {
+        match (self, value) {
+            (FieldType::Date, toml::Value::String(s)) => s.parse::<PartialDate>().is_ok(),
+            _ => matches!(
+                (self, value),
+                (FieldType::String, toml::Value::String(_))
+                    | (FieldType::Integer, toml::Value::Integer(_))
+                    | (
+                        FieldType::Float,
+                        toml::Value::Float(_) | toml::Value::Integer(_)
+                    )
+                    | (FieldType::Boolean, toml::Value::Boolean(_))
+                    | (FieldType::Date, toml::Value::Datetime(_))
+                    | (FieldType::Array, toml::Value::Array(_))
+                    | (FieldType::Table, toml::Value::Table(_))
+            ),
+        }
+    }
+}
+
+/// Serializes toml table as json object, so datetimes become plain strings
+fn serialize_toml_table<S: serde::Serializer>(
+    table: &toml::Table,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    toml_to_json(toml::Value::Table(table.clone())).serialize(serializer)
+}
+
+pub fn toml_to_json(value: toml::Value) -> serde_json::Value {
+    match value {
+        toml::Value::String(s) => json!(s),
+        toml::Value::Integer(i) => json!(i),
+        toml::Value::Float(f) => json!(f),
+        toml::Value::Boolean(b) => json!(b),
+        toml::Value::Datetime(d) => json!(d.to_string()),
+        toml::Value::Array(a) => a.into_iter().map(toml_to_json).collect(),
+        toml::Value::Table(t) => t.into_iter().map(|(k, v)| (k, toml_to_json(v))).collect(),
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -117,15 +696,46 @@ pub struct CountryData {
     pub config: CountryConfig,
     pub land: MultiPolygon,
     pub markers: Vec<Marker>,
+    /// Properties of territory features passed through to the output
+    pub properties: JsonObject,
+    pub regions: Vec<RegionData>,
+    pub urban_areas: Vec<UrbanArea>,
+    /// Ids of included countries with this one as parent, also if merged into it
+    pub children: Vec<String>,
+    /// Territorial waters, see `waters` of processing item
+    pub waters: MultiPolygon,
+}
+
+/// Polygon with `type: "city"` in country geojson, kept separate from land
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UrbanArea {
+    pub land: MultiPolygon,
+    /// Title of the city or capital marker with the same `title` or `id`
+    pub city: Option<String>,
+    pub properties: JsonObject,
+}
+
+impl UrbanArea {
+    /// Finds the city or capital marker with the same `title` or `id` property
+    pub fn find_city<'a>(&self, markers: &'a [Marker]) -> Option<&'a Marker> {
+        let title = self.properties.get("title");
+        let id = self.properties.get("id");
+
+        markers
+            .iter()
+            .filter(|m| m.ty.id == MarkerType::CITY || m.ty.id == MarkerType::CAPITAL)
+            .find(|m| {
+                title.is_some_and(|t| t.as_str() == Some(m.title.as_str()))
+                    || id.is_some_and(|id| m.properties.get("id") == Some(id))
+            })
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct CountryPolyProps {
-    id: String,
-    r#type: String,
-    fill: String,
-    stroke: String,
-    tags: Vec<String>,
+pub struct RegionData {
+    pub id: String,
+    pub config: RegionConfig,
+    pub land: MultiPolygon,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -134,23 +744,34 @@ pub struct Marker {
     pub title: String,
     pub description: String,
     pub ty: MarkerType,
+    /// Other properties of the marker passed through to the output
+    pub properties: JsonObject,
 }
 
 impl ToFeature for Marker {
     fn to_feature(&self) -> geojson::Feature {
+        let mut properties = JsonObject::new();
+
+        if let Some(icon) = &self.ty.icon {
+            properties.insert("icon".to_owned(), json!(icon));
+        }
+        if let Some(min_zoom) = self.ty.min_zoom {
+            properties.insert("min_zoom".to_owned(), json!(min_zoom));
+        }
+
+        properties.extend(self.properties.clone());
+        properties.extend([
+            ("title".to_owned(), json!(self.title)),
+            ("description".to_owned(), json!(self.description)),
+            ("type".to_owned(), json!(self.ty.to_str())),
+        ]);
+
         geojson::Feature {
             geometry: Some(geojson::Geometry::new(Value::Point(vec![
                 self.coordinates.x(),
                 self.coordinates.y(),
             ]))),
-            properties: Some(
-                serde_json::Map::from_iter([
-                    ("title".to_owned(), json!(self.title)),
-                    ("description".to_owned(), json!(self.description)),
-                    ("type".to_owned(), json!(self.ty.to_str())),
-                ])
-                .into(),
-            ),
+            properties: Some(properties),
 
             bbox: None,
             id: None,
@@ -159,21 +780,52 @@ impl ToFeature for Marker {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub enum MarkerType {
-    Capital,
-    City,
-    Landmark,
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+pub struct MarkerType {
+    /// Identifier used in `type` property of markers in country geojson
+    pub id: String,
+    /// Value of `type` property in output. Defaults to `id`
+    pub r#type: Option<String>,
+    /// Alternative identifiers of this type
+    pub aliases: Option<Vec<String>>,
+    pub icon: Option<String>,
+    pub min_zoom: Option<f64>,
 }
 
 impl MarkerType {
-    pub fn to_str(&self) -> &'static str {
-        match self {
-            MarkerType::Capital => "capital",
-            MarkerType::City => "city",
-            MarkerType::Landmark => "landmark-0",
+    pub const CAPITAL: &'static str = "capital";
+    pub const CITY: &'static str = "city";
+
+    fn new(id: &str, r#type: Option<&str>, aliases: &[&str]) -> MarkerType {
+        MarkerType {
+            id: id.to_owned(),
+            r#type: r#type.map(|t| t.to_owned()),
+            aliases: Some(aliases.iter().map(|a| a.to_string()).collect()),
+            icon: None,
+            min_zoom: None,
         }
     }
+
+    /// Marker types available in every project
+    pub fn builtin() -> Vec<MarkerType> {
+        vec![
+            MarkerType::new(MarkerType::CAPITAL, None, &["capital-city"]),
+            MarkerType::new(MarkerType::CITY, None, &[]),
+            MarkerType::new("landmark", Some("landmark-0"), &[]),
+        ]
+    }
+
+    pub fn to_str(&self) -> &str {
+        self.r#type.as_deref().unwrap_or(&self.id)
+    }
+
+    pub fn is_match(&self, ty: &str) -> bool {
+        self.id == ty
+            || self
+                .aliases
+                .as_ref()
+                .is_some_and(|aliases| aliases.iter().any(|a| a == ty))
+    }
 }
 
 pub enum Territory {
@@ -181,12 +833,6 @@ pub enum Territory {
     MultiPolygon(MultiPolygon),
 }
 
-impl ToTerritory for MultiPolygon {
-    fn to_territory(&self) -> Territory {
-        Territory::MultiPolygon(self.clone())
-    }
-}
-
 impl ToFeature for MultiPolygon {
     fn to_feature(&self) -> geojson::Feature {
         geojson::Feature {
@@ -200,6 +846,44 @@ impl ToFeature for MultiPolygon {
     }
 }
 
+impl ToFeatures for LineData {
+    fn to_features(&self) -> Vec<geojson::Feature> {
+        let serde_json::Value::Object(config) = json!(self.config) else {
+            unreachable!()
+        };
+
+        let mut properties: JsonObject = config.into_iter().filter(|(_, v)| !v.is_null()).collect();
+        properties.extend([
+            ("id".to_owned(), json!(self.id)),
+            ("type".to_owned(), json!(self.kind.to_str())),
+        ]);
+
+        self.geo
+            .features
+            .iter()
+            .map(|f| {
+                let mut feature_properties = f.properties.clone().unwrap_or_default();
+                feature_properties.extend(properties.clone());
+
+                geojson::Feature {
+                    geometry: f.geometry.clone(),
+                    properties: Some(feature_properties),
+
+                    bbox: None,
+                    id: None,
+                    foreign_members: None,
+                }
+            })
+            .collect()
+    }
+}
+
+impl ToFeatures for Vec<LineData> {
+    fn to_features(&self) -> Vec<geojson::Feature> {
+        self.iter().flat_map(|l| l.to_features()).collect()
+    }
+}
+
 impl ToFeatures for Vec<Marker> {
     fn to_features(&self) -> Vec<geojson::Feature> {
         self.iter().map(|m| m.to_feature()).collect()
@@ -208,18 +892,18 @@ impl ToFeatures for Vec<Marker> {
 
 impl ToFeatures for CountryData {
     fn to_features(&self) -> Vec<geojson::Feature> {
+        let mut properties = self.properties.clone();
+        properties.extend([
+            ("id".to_owned(), json!(self.id)),
+            ("type".to_owned(), json!("country")),
+            ("fill".to_owned(), json!(self.config.fill)),
+            ("stroke".to_owned(), json!(self.config.stroke)),
+            ("tags".to_owned(), json!(self.config.tags)),
+        ]);
+
         let land = geojson::Feature {
             geometry: Some(geojson::Geometry::from(&self.land)),
-            properties: Some(
-                serde_json::Map::from_iter([
-                    ("id".to_owned(), json!(self.id)),
-                    ("type".to_owned(), json!("country")),
-                    ("fill".to_owned(), json!(self.config.fill)),
-                    ("stroke".to_owned(), json!(self.config.stroke)),
-                    ("tags".to_owned(), json!(self.config.tags)),
-                ])
-                .into(),
-            ),
+            properties: Some(properties),
 
             bbox: None,
             id: None,
@@ -227,6 +911,64 @@ impl ToFeatures for CountryData {
         };
 
         let mut features = vec![land];
+        features.extend(self.regions.iter().map(|region| {
+            let mut properties = JsonObject::new();
+            properties.extend([
+                ("id".to_owned(), json!(region.id)),
+                ("country".to_owned(), json!(self.id)),
+                ("type".to_owned(), json!("region")),
+                ("name".to_owned(), json!(region.config.name)),
+                (
+                    "fill".to_owned(),
+                    json!(region.config.fill.as_ref().unwrap_or(&self.config.fill)),
+                ),
+                (
+                    "stroke".to_owned(),
+                    json!(region.config.stroke.as_ref().unwrap_or(&self.config.stroke)),
+                ),
+            ]);
+
+            geojson::Feature {
+                geometry: Some(geojson::Geometry::from(&region.land)),
+                properties: Some(properties),
+
+                bbox: None,
+                id: None,
+                foreign_members: None,
+            }
+        }));
+        features.extend(self.urban_areas.iter().map(|area| {
+            let mut properties = area.properties.clone();
+            properties.extend([
+                ("country".to_owned(), json!(self.id)),
+                ("type".to_owned(), json!("urban_area")),
+                ("city".to_owned(), json!(area.city)),
+            ]);
+
+            geojson::Feature {
+                geometry: Some(geojson::Geometry::from(&area.land)),
+                properties: Some(properties),
+
+                bbox: None,
+                id: None,
+                foreign_members: None,
+            }
+        }));
+        if !self.waters.0.is_empty() {
+            features.push(geojson::Feature {
+                geometry: Some(geojson::Geometry::from(&self.waters)),
+                properties: Some(JsonObject::from_iter([
+                    ("country".to_owned(), json!(self.id)),
+                    ("type".to_owned(), json!("waters")),
+                    ("fill".to_owned(), json!(self.config.fill)),
+                    ("stroke".to_owned(), json!(self.config.stroke)),
+                ])),
+
+                bbox: None,
+                id: None,
+                foreign_members: None,
+            });
+        }
         features.extend(self.markers.to_features());
 
         features
@@ -260,53 +1002,99 @@ impl ToCollection for Vec<geojson::Feature> {
 }
 
 impl ToSplitGeo for FeatureCollection {
-    fn split_geo(&self) -> (Vec<Marker>, Vec<Territory>) {
+    fn split_geo(
+        &self,
+        marker_types: &[MarkerType],
+    ) -> (Vec<Marker>, Vec<Territory>, JsonObject, Vec<UrbanArea>) {
         let mut markers: Vec<Marker> = vec![];
         let mut territories: Vec<Territory> = vec![];
+        let mut territory_properties = JsonObject::new();
+        let mut urban_areas: Vec<UrbanArea> = vec![];
 
         self.features.iter().for_each(|f| {
-            let properties = f.properties.clone().unwrap();
+            let mut properties = f.properties.clone().unwrap_or_default();
 
             let geometry: Geometry = f.geometry.clone().unwrap().try_into().unwrap();
 
             match geometry {
                 Geometry::Point(p) => {
-                    let ty = match properties
-                        .get("type")
-                        .expect("Missing marker type")
-                        .to_string()
-                        .trim_matches('"')
-                    {
-                        "capital" | "capital-city" => MarkerType::Capital,
-                        "city" => MarkerType::City,
-                        "landmark" => MarkerType::Landmark,
-
-                        t => panic!("Invalid marker type: {}", t),
+                    let ty = properties
+                        .remove("type")
+                        .map(json_to_string)
+                        .expect("Missing marker type");
+
+                    let ty = match marker_types.iter().find(|t| t.is_match(&ty)) {
+                        Some(t) => t.clone(),
+                        None => panic!(
+                            "Invalid marker type: {}, expected one of: {}",
+                            ty,
+                            marker_types
+                                .iter()
+                                .map(|t| t.id.as_str())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ),
                     };
 
                     markers.push(Marker {
                         coordinates: p,
                         title: properties
-                            .get("title")
-                            .expect("Missing marker title")
-                            .to_string(),
+                            .remove("title")
+                            .map(json_to_string)
+                            .expect("Missing marker title"),
                         description: properties
-                            .get("description")
-                            .unwrap_or(&json!(""))
-                            .to_string(),
+                            .remove("description")
+                            .map(json_to_string)
+                            .unwrap_or_default(),
                         ty,
+                        properties,
                     })
                 }
 
-                Geometry::MultiPolygon(mp) => territories.push(Territory::MultiPolygon(mp)),
+                Geometry::Polygon(_) | Geometry::MultiPolygon(_)
+                    if properties.get("type").and_then(|t| t.as_str())
+                        == Some(MarkerType::CITY) =>
+                {
+                    properties.remove("type");
 
-                Geometry::Polygon(p) => territories.push(Territory::Polygon(p)),
+                    urban_areas.push(UrbanArea {
+                        land: match geometry {
+                            Geometry::Polygon(p) => p.to_mp(),
+                            Geometry::MultiPolygon(mp) => mp,
+                            _ => unreachable!(),
+                        },
+                        city: None,
+                        properties,
+                    })
+                }
+
+                Geometry::MultiPolygon(mp) => {
+                    territory_properties.extend(properties);
+                    territories.push(Territory::MultiPolygon(mp))
+                }
+
+                Geometry::Polygon(p) => {
+                    territory_properties.extend(properties);
+                    territories.push(Territory::Polygon(p))
+                }
+
+                Geometry::LineString(_) | Geometry::MultiLineString(_) => {
+                    panic!("Unexpected line in country geojson, use roads/, rivers/ or railways/")
+                }
 
                 _ => panic!("Unexpected geometry type"),
             }
         });
 
-        (markers, territories)
+        (markers, territories, territory_properties, urban_areas)
+    }
+}
+
+/// Extracts string without surrounding quotes, other values are formatted as json
+pub fn json_to_string(value: serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s,
+        value => value.to_string(),
     }
 }
 
@@ -324,24 +1112,15 @@ pub trait ToFeatures {
     fn to_features(&self) -> Vec<geojson::Feature>;
 }
 
-pub trait ToCountryFeature {
-    fn to_country_feature(&self, id: &String, config: &CountryConfig) -> geojson::Feature;
-}
-
 pub trait ToCollection {
     fn to_collection(self) -> geojson::FeatureCollection;
 }
 
 pub trait ToSplitGeo {
-    fn split_geo(&self) -> (Vec<Marker>, Vec<Territory>);
-}
-
-pub trait ToTerritory {
-    fn to_territory(&self) -> Territory;
-}
-
-pub trait UnsplitGeo {
-    fn unsplit_geo(self) -> FeatureCollection;
+    fn split_geo(
+        &self,
+        marker_types: &[MarkerType],
+    ) -> (Vec<Marker>, Vec<Territory>, JsonObject, Vec<UrbanArea>);
 }
 
 pub trait ToMultiPolygon {
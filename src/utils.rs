@@ -1,48 +1,805 @@
-use std::{fs, path::Path};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
-use geo::{BooleanOps, MultiPolygon};
-use geojson::GeoJson;
+use brotli::enc::BrotliEncoderParams;
+use flate2::write::GzEncoder;
+
+use geo::{
+    Area, BooleanOps, BoundingRect, Buffer, Closest, ClosestPoint, Intersects, MultiPolygon,
+    Polygon, Rect,
+};
+use geojson::{FeatureCollection, GeoJson, Value};
+use serde::Serialize;
 use wax::{Glob, Pattern};
 
-use crate::types::{Config, CountryConfig, CountryData, Territory, ToMultiPolygon, ToSplitGeo};
+use crate::date::PartialDate;
+use crate::filter::PropertiesFilter;
+use crate::types::{
+    Compression, Config, CountryConfig, CountryData, FieldConfig, JsonFormat, LineConfig, LineData,
+    LineKind, MarkerOutsideAction, MarkerType, MarkersConfig, ProcessingConfig, ProjectPaths,
+    RegionConfig, RegionData, Territory, TimelineEvent, TimelineEventType, ToMultiPolygon,
+    ToSplitGeo, WatersConfig, WatersOverlap,
+};
+
+pub fn read_config(paths: &ProjectPaths) -> Config {
+    let table = toml::from_str::<toml::Table>(&fs::read_to_string(&paths.config).unwrap());
 
-pub fn read_config() -> Config {
-    let c = toml::from_str::<Config>(&fs::read_to_string("config.toml").unwrap());
+    let table = match table {
+        Ok(table) => resolve_processing(table),
+        Err(err) => panic!("Invalid config: {}", err),
+    };
 
-    match c {
+    match toml::Value::Table(table).try_into() {
         Ok(c) => c,
         Err(err) => panic!("Invalid config: {}", err),
     }
 }
 
-pub fn get_country(id: String) -> CountryData {
-    let country_folder = Path::new(".").join("countries").join(&id);
+/// Applies `processing_defaults` and `extends` to every processing item
+fn resolve_processing(mut table: toml::Table) -> toml::Table {
+    let defaults = match table.get("processing_defaults") {
+        Some(toml::Value::Table(defaults)) => defaults.clone(),
+        Some(_) => panic!("Invalid config: processing_defaults should be a table"),
+        None => toml::Table::new(),
+    };
+
+    let items: Vec<toml::Table> = match table.get("processing") {
+        Some(toml::Value::Array(items)) => items
+            .iter()
+            .map(|item| match item {
+                toml::Value::Table(item) => item.clone(),
+                _ => panic!("Invalid config: processing should be an array of tables"),
+            })
+            .collect(),
+        _ => return table,
+    };
+
+    let resolved = items
+        .iter()
+        .map(|item| toml::Value::Table(resolve_processing_item(item, &items, &defaults, vec![])))
+        .collect();
+
+    table.insert("processing".to_owned(), toml::Value::Array(resolved));
+
+    table
+}
+
+fn resolve_processing_item(
+    item: &toml::Table,
+    items: &[toml::Table],
+    defaults: &toml::Table,
+    mut chain: Vec<String>,
+) -> toml::Table {
+    if let Some(toml::Value::String(name)) = item.get("name") {
+        if chain.contains(name) {
+            panic!("Invalid config: processing item {name} extends itself");
+        }
+        chain.push(name.clone());
+    }
+
+    let mut resolved = match item.get("extends") {
+        Some(toml::Value::String(parent)) => {
+            let parent_item = items
+                .iter()
+                .find(|i| i.get("name").and_then(|n| n.as_str()) == Some(parent))
+                .unwrap_or_else(|| {
+                    panic!("Invalid config: no processing item named {parent} to extend")
+                });
+
+            let mut resolved = resolve_processing_item(parent_item, items, defaults, chain);
+            resolved.remove("name");
+            resolved.remove("extends");
+            resolved
+        }
+        Some(_) => panic!("Invalid config: extends should be a name of processing item"),
+        None => defaults.clone(),
+    };
+
+    merge_toml(&mut resolved, item.clone());
+
+    resolved
+}
+
+/// Merges `overlay` into `base`. Tables are merged recursively, arrays of tables (e.g.
+/// `countries_rewrite`) are concatenated and other values, including empty arrays, are replaced
+fn merge_toml(base: &mut toml::Table, overlay: toml::Table) {
+    let is_tables = |a: &Vec<toml::Value>| a.iter().all(|v| v.is_table());
+
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => {
+                merge_toml(base, overlay)
+            }
+            (Some(toml::Value::Array(base)), toml::Value::Array(overlay))
+                if !overlay.is_empty() && is_tables(base) && is_tables(&overlay) =>
+            {
+                base.extend(overlay)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+pub fn read_country_config(id: &str, paths: &ProjectPaths) -> CountryConfig {
+    let country_folder = paths.country_folder(id);
 
     let config = toml::from_str::<CountryConfig>(
         &fs::read_to_string(country_folder.join("country.toml")).unwrap(),
     );
 
-    let config = match config {
+    match config {
         Ok(c) => c,
-        Err(err) => panic!("Invalid config: {err}"),
-    };
+        Err(err) => panic!("Invalid config of country {id}: {err}"),
+    }
+}
+
+/// Checks custom fields of a country against their declarations. Returns a description of every
+/// problem found
+pub fn validate_fields(
+    id: &str,
+    config: &CountryConfig,
+    fields: &BTreeMap<String, FieldConfig>,
+) -> Vec<String> {
+    let mut problems = vec![];
 
-    let geo_str = fs::read_to_string(country_folder.join("country.geojson")).unwrap();
+    for (name, field) in fields {
+        match config.extra.get(name) {
+            Some(value) if !field.r#type.is_match(value) => problems.push(format!(
+                "Field {name} of country {id} should be {:?}, got {value}",
+                field.r#type
+            )),
+            Some(_) => {}
+            None if field.required.unwrap_or(false) => {
+                problems.push(format!("Missing required field {name} in country {id}"))
+            }
+            None => {}
+        }
+    }
+
+    for name in config.extra.keys() {
+        if !fields.contains_key(name) {
+            problems.push(format!("Undeclared field {name} in country {id}"));
+        }
+    }
+
+    problems
+}
+
+/// Checks custom fields set by `countries_rewrite` of a processing item against `fields`
+pub fn validate_rewrite_fields(
+    processing_item: &ProcessingConfig,
+    fields: &BTreeMap<String, FieldConfig>,
+) -> Vec<String> {
+    let item = &processing_item.output_folder;
+    let mut problems = vec![];
+
+    for rewrite in processing_item.countries_rewrite.iter().flatten() {
+        for (name, value) in &rewrite.properties.extra {
+            match fields.get(name) {
+                Some(field) if !field.r#type.is_match(value) => problems.push(format!(
+                    "Field {name} in countries_rewrite of {item} should be {:?}, got {value}",
+                    field.r#type
+                )),
+                Some(_) => {}
+                None => problems.push(format!(
+                    "Undeclared field {name} in countries_rewrite of {item}"
+                )),
+            }
+        }
+    }
+
+    problems
+}
+
+pub fn read_feature_collection(path: &Path) -> FeatureCollection {
+    let geo_str = fs::read_to_string(path).unwrap();
     let geo: GeoJson = geo_str.parse().unwrap();
 
-    let geo = match geo {
+    match geo {
         GeoJson::FeatureCollection(coll) => coll,
-        _ => panic!("Invalid geojson, expected FeatureCollection"),
-    };
+        _ => panic!(
+            "Invalid geojson {}, expected FeatureCollection",
+            path.display()
+        ),
+    }
+}
+
+/// Reads and dissolves territories of a geojson file, ignoring markers
+pub fn read_land(path: &Path, config: &Config) -> MultiPolygon {
+    let marker_types = config.markers.clone().unwrap_or_default().marker_types();
+    let (_, territories, _, _) = read_feature_collection(path).split_geo(&marker_types);
+
+    dissolve_territories(territories)
+}
+
+/// Geometry versions of a country from `history/<date>.geojson` files, sorted by date.
+/// Each file holds borders in effect from its date until the next one
+pub fn history_files(country_folder: &Path) -> Vec<(PartialDate, PathBuf)> {
+    let history_folder = country_folder.join("history");
+
+    if !history_folder.is_dir() {
+        return vec![];
+    }
+
+    let mut files: Vec<(PartialDate, PathBuf)> = fs::read_dir(&history_folder)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "geojson"))
+        .map(|path| {
+            let stem = path.file_stem().unwrap().to_string_lossy().to_string();
+
+            match stem.parse() {
+                Ok(date) => (date, path),
+                Err(err) => panic!("Invalid history file {}: {err}", path.display()),
+            }
+        })
+        .collect();
+
+    files.sort_by_key(|(date, _)| *date);
+
+    files
+}
+
+fn feature_date(feature: &geojson::Feature, property: &str) -> Option<PartialDate> {
+    let value = feature.properties.as_ref()?.get(property)?;
+
+    match serde_json::from_value(value.clone()) {
+        Ok(date) => Some(date),
+        Err(err) => panic!("Invalid {property} {value}: {err}"),
+    }
+}
+
+fn is_valid_feature(feature: &geojson::Feature, date: Option<&PartialDate>) -> bool {
+    let from = feature_date(feature, "valid_from");
+    let to = feature_date(feature, "valid_to");
+
+    match date {
+        Some(date) => from.is_none_or(|from| from <= *date) && to.is_none_or(|to| *date < to),
+        None => to.is_none(),
+    }
+}
+
+/// Keeps features valid at `date` according to their `valid_from` and `valid_to` properties
+/// and removes these properties. Without date only features without `valid_to` are kept
+pub fn retain_valid_features(geo: &mut FeatureCollection, date: Option<&PartialDate>) {
+    geo.features.retain(|f| is_valid_feature(f, date));
+
+    for feature in geo.features.iter_mut() {
+        if let Some(properties) = feature.properties.as_mut() {
+            properties.remove("valid_from");
+            properties.remove("valid_to");
+        }
+    }
+}
+
+/// Country config with all its geometry versions, loaded once and reused across dates
+#[derive(Debug, Clone)]
+pub struct CountrySource {
+    pub id: String,
+    pub config: CountryConfig,
+    /// Original `country.geojson` without date, followed by history files sorted by the date
+    /// they are in effect from
+    versions: Vec<(Option<PartialDate>, FeatureCollection)>,
+    regions: Vec<(String, RegionConfig, FeatureCollection)>,
+}
+
+impl CountrySource {
+    pub fn load(id: &str, paths: &ProjectPaths) -> CountrySource {
+        let country_folder = paths.country_folder(id);
+
+        let mut versions = vec![(
+            None,
+            read_feature_collection(&country_folder.join("country.geojson")),
+        )];
+        versions.extend(
+            history_files(&country_folder)
+                .into_iter()
+                .map(|(date, path)| (Some(date), read_feature_collection(&path))),
+        );
+
+        CountrySource {
+            id: id.to_owned(),
+            config: read_country_config(id, paths),
+            versions,
+            regions: read_regions(id, &country_folder),
+        }
+    }
+
+    /// Index of the version valid at `date`: the latest history file not after `date`,
+    /// otherwise `country.geojson`. Without date the latest version
+    fn version_at(&self, date: Option<&PartialDate>) -> usize {
+        match date {
+            Some(date) => self
+                .versions
+                .iter()
+                .rposition(|(from, _)| from.as_ref().is_some_and(|from| from <= date))
+                .unwrap_or(0),
+            None => self.versions.len() - 1,
+        }
+    }
+
+    /// Geometry valid at `date`
+    pub fn geo_at(&self, date: Option<&PartialDate>) -> FeatureCollection {
+        let mut geo = self.versions[self.version_at(date)].1.clone();
+        retain_valid_features(&mut geo, date);
+
+        geo
+    }
+
+    /// Identifies the geometry, regions and existence of the country at `date`.
+    /// Equal states mean equal countries
+    pub fn state_at(
+        &self,
+        date: Option<&PartialDate>,
+    ) -> Option<(usize, Vec<bool>, Vec<Vec<bool>>)> {
+        if let Some(date) = date {
+            match self.config.exists_at(date) {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(err) => panic!("Invalid foundation_date of country {}: {err}", self.id),
+            }
+        }
+
+        let version = self.version_at(date);
+        let valid = self.versions[version]
+            .1
+            .features
+            .iter()
+            .map(|f| is_valid_feature(f, date))
+            .collect();
+        let regions = self
+            .regions
+            .iter()
+            .map(|(_, _, geo)| {
+                geo.features
+                    .iter()
+                    .map(|f| is_valid_feature(f, date))
+                    .collect()
+            })
+            .collect();
+
+        Some((version, valid, regions))
+    }
+
+    /// Foundation, dissolution and border changes of the country
+    pub fn timeline(&self) -> Vec<TimelineEvent> {
+        let event = |date: PartialDate, event: TimelineEventType| TimelineEvent {
+            date,
+            country: self.id.clone(),
+            event,
+        };
+
+        let mut events = vec![];
+
+        if let Ok(Some(date)) = self.config.foundation() {
+            events.push(event(date, TimelineEventType::Founded));
+        }
+        if let Some(date) = self.config.dissolution_date {
+            events.push(event(date, TimelineEventType::Dissolved));
+        }
+
+        for (from, geo) in &self.versions {
+            if let Some(date) = from {
+                events.push(event(*date, TimelineEventType::Borders));
+            }
+
+            for feature in &geo.features {
+                let is_territory = feature
+                    .geometry
+                    .as_ref()
+                    .is_some_and(|g| matches!(g.value, Value::Polygon(_) | Value::MultiPolygon(_)));
+
+                if is_territory {
+                    for property in ["valid_from", "valid_to"] {
+                        if let Some(date) = feature_date(feature, property) {
+                            events.push(event(date, TimelineEventType::Borders));
+                        }
+                    }
+                }
+            }
+        }
+
+        events.sort_by_key(|e| e.date);
+        events.dedup_by(|a, b| a.date == b.date && a.event == b.event);
+
+        events
+    }
+}
+
+/// Reads regions of a country from `regions/<id>/region.toml` and `region.geojson`, sorted by id
+fn read_regions(
+    country_id: &str,
+    country_folder: &Path,
+) -> Vec<(String, RegionConfig, FeatureCollection)> {
+    let regions_folder = country_folder.join("regions");
+
+    if !regions_folder.is_dir() {
+        return vec![];
+    }
+
+    let mut regions: Vec<_> = fs::read_dir(&regions_folder)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .map(|path| {
+            let id = path.file_name().unwrap().to_string_lossy().to_string();
+
+            let config = match toml::from_str::<RegionConfig>(
+                &fs::read_to_string(path.join("region.toml")).unwrap(),
+            ) {
+                Ok(c) => c,
+                Err(err) => panic!("Invalid config of region {id} of country {country_id}: {err}"),
+            };
+
+            (
+                id,
+                config,
+                read_feature_collection(&path.join("region.geojson")),
+            )
+        })
+        .collect();
+
+    regions.sort_by(|a, b| a.0.cmp(&b.0));
+
+    regions
+}
+
+/// Parent of every country by its id
+pub fn country_parents(sources: &[CountrySource]) -> BTreeMap<String, Option<String>> {
+    sources
+        .iter()
+        .map(|s| (s.id.clone(), s.config.parent.clone()))
+        .collect()
+}
+
+/// Ids of parent, grandparent and further ancestors of a country, see [`country_parents`]
+pub fn country_ancestors(
+    id: &str,
+    parents: &BTreeMap<String, Option<String>>,
+) -> Result<Vec<String>, String> {
+    let mut ancestors: Vec<String> = vec![];
+    let mut current = id;
+
+    loop {
+        let Some(parent) = parents.get(current) else {
+            return Err(format!("Unknown parent {current} of country {id}"));
+        };
+
+        let Some(parent) = parent else {
+            return Ok(ancestors);
+        };
+
+        if parent == id || ancestors.contains(parent) {
+            return Err(format!("Country {id} is its own ancestor"));
+        }
+
+        ancestors.push(parent.clone());
+        current = parent;
+    }
+}
+
+/// Reads roads, rivers and railways of the project, sorted by kind and id
+pub fn read_lines(paths: &ProjectPaths) -> Vec<LineData> {
+    let mut lines = vec![];
+
+    for kind in LineKind::ALL {
+        let folder = paths.dir.join(kind.folder());
+
+        if !folder.is_dir() {
+            continue;
+        }
+
+        let mut ids: Vec<String> = fs::read_dir(&folder)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.is_dir())
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        ids.sort();
+
+        for id in ids {
+            let line_folder = paths.line_folder(kind, &id);
+
+            let config = toml::from_str::<LineConfig>(
+                &fs::read_to_string(line_folder.join("line.toml")).unwrap(),
+            );
+            let config = match config {
+                Ok(c) => c,
+                Err(err) => panic!("Invalid config of {} {id}: {err}", kind.to_str()),
+            };
+
+            let geo = read_feature_collection(&line_folder.join("line.geojson"));
+
+            let is_line = |f: &geojson::Feature| {
+                f.geometry.as_ref().is_some_and(|g| {
+                    matches!(g.value, Value::LineString(_) | Value::MultiLineString(_))
+                })
+            };
+            if !geo.features.iter().all(is_line) {
+                panic!(
+                    "Invalid geojson of {} {id}, expected only LineString and MultiLineString features",
+                    kind.to_str()
+                );
+            }
+
+            lines.push(LineData {
+                id,
+                kind,
+                config,
+                geo,
+            });
+        }
+    }
+
+    lines
+}
+
+/// Lines with features valid at `date` and their properties filtered like in [`get_country`]
+pub fn lines_at(
+    lines: &[LineData],
+    properties_filter: &PropertiesFilter,
+    date: Option<&PartialDate>,
+) -> Vec<LineData> {
+    lines
+        .iter()
+        .map(|line| {
+            let mut line = line.clone();
+            retain_valid_features(&mut line.geo, date);
+            for feature in line.geo.features.iter_mut() {
+                if let Some(properties) = feature.properties.as_mut() {
+                    properties_filter.filter(properties);
+                }
+            }
+
+            line
+        })
+        .collect()
+}
+
+/// Identifies features of lines valid at `date`, see [`CountrySource::state_at`]
+pub fn line_states_at(lines: &[LineData], date: Option<&PartialDate>) -> Vec<Vec<bool>> {
+    lines
+        .iter()
+        .map(|line| {
+            line.geo
+                .features
+                .iter()
+                .map(|f| is_valid_feature(f, date))
+                .collect()
+        })
+        .collect()
+}
+
+/// Countries, line layers and land mask of a project, loaded once and reused by all processing
+/// items
+pub struct ProjectLayers {
+    pub countries: Vec<CountrySource>,
+    pub lines: Vec<LineData>,
+    pub land_mask: Option<LandMask>,
+    /// Compiled `[properties]` of the config
+    pub properties: PropertiesFilter,
+    /// Land which should be covered by countries, loaded only with `with_world`
+    pub world_land: Option<MultiPolygon>,
+}
+
+impl ProjectLayers {
+    pub fn load(config: &Config, paths: &ProjectPaths, with_world: bool) -> ProjectLayers {
+        let land_mask = config.land_mask.as_ref().map(|mask| {
+            if mask.path.is_none() && mask.water.is_none() {
+                panic!("Invalid land_mask, expected path or water");
+            }
+
+            LandMask {
+                land: mask
+                    .path
+                    .as_ref()
+                    .map(|p| read_land(&paths.resolve(p), config)),
+                water: mask
+                    .water
+                    .as_ref()
+                    .map(|p| read_land(&paths.resolve(p), config)),
+            }
+        });
+
+        let world_land = match &land_mask {
+            _ if !with_world => None,
+            Some(mask) => mask.world(),
+            None => read_nature_land(config, paths),
+        };
+
+        ProjectLayers {
+            countries: load_country_sources(config, paths),
+            lines: read_lines(paths),
+            land_mask,
+            properties: PropertiesFilter::new(&config.properties.clone().unwrap_or_default()),
+            world_land,
+        }
+    }
+}
+
+/// See [`LandMaskConfig`]
+pub struct LandMask {
+    land: Option<MultiPolygon>,
+    water: Option<MultiPolygon>,
+}
+
+impl LandMask {
+    /// Clips `land` to the mask and returns the trimmed area in square degrees
+    pub fn clip(&self, land: &mut MultiPolygon) -> f64 {
+        let area = land.unsigned_area();
+
+        if let Some(mask) = &self.land {
+            *land = land.intersection(mask);
+        }
+        if let Some(water) = &self.water {
+            *land = land.difference(water);
+        }
+
+        (area - land.unsigned_area()).max(0.0)
+    }
+
+    fn world(&self) -> Option<MultiPolygon> {
+        let land = match (&self.land, &self.water) {
+            (Some(land), _) => land.clone(),
+            (None, Some(water)) => water.bounding_rect()?.to_polygon().to_mp(),
+            (None, None) => return None,
+        };
+
+        Some(match &self.water {
+            Some(water) => land.difference(water),
+            None => land,
+        })
+    }
+}
+
+/// Union of `nature/*.geojson` layers except `water.geojson`
+fn read_nature_land(config: &Config, paths: &ProjectPaths) -> Option<MultiPolygon> {
+    let nature_folder = paths.dir.join("nature");
+
+    if !nature_folder.is_dir() {
+        return None;
+    }
+
+    let land = fs::read_dir(&nature_folder)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension().is_some_and(|e| e == "geojson")
+                && path.file_name().is_some_and(|n| n != "water.geojson")
+        })
+        .fold(MultiPolygon::new(vec![]), |land, path| {
+            land.union(&read_land(&path, config))
+        });
+
+    (!land.0.is_empty()).then_some(land)
+}
+
+/// Polygons of `world_land` not covered by any country. Without `world_land` only holes enclosed
+/// by countries are found
+pub fn unclaimed_land(
+    countries: &[CountryData],
+    world_land: Option<&MultiPolygon>,
+) -> Vec<Polygon> {
+    let claimed = countries
+        .iter()
+        .fold(MultiPolygon::new(vec![]), |a, c| a.union(&c.land));
+
+    match world_land {
+        Some(world) => world.difference(&claimed).0,
+        None => claimed
+            .0
+            .iter()
+            .flat_map(|p| p.interiors())
+            .map(|ring| Polygon::new(ring.clone(), vec![]))
+            .collect(),
+    }
+}
+
+/// Unclaimed polygons smaller than `max_area` touching at least two countries
+pub fn find_slivers(
+    countries: &[CountryData],
+    unclaimed: &[Polygon],
+    max_area: f64,
+) -> Vec<String> {
+    unclaimed
+        .iter()
+        .filter(|p| p.unsigned_area() < max_area)
+        .filter_map(|p| {
+            let neighbors: Vec<&str> = countries
+                .iter()
+                .filter(|c| c.land.intersects(p))
+                .map(|c| c.id.as_str())
+                .collect();
+
+            if neighbors.len() < 2 {
+                return None;
+            }
+
+            let point = p.exterior().0.first()?;
+
+            Some(format!(
+                "Gap of {:.6} sq. degrees between countries {} at ({}, {})",
+                p.unsigned_area(),
+                neighbors.join(", "),
+                point.x,
+                point.y
+            ))
+        })
+        .collect()
+}
+
+pub fn load_country_sources(config: &Config, paths: &ProjectPaths) -> Vec<CountrySource> {
+    config
+        .main
+        .layers
+        .iter()
+        .map(|id| CountrySource::load(id, paths))
+        .collect()
+}
+
+pub fn get_country(
+    source: &CountrySource,
+    config: &Config,
+    properties_filter: &PropertiesFilter,
+    date: Option<&PartialDate>,
+) -> CountryData {
+    let id = &source.id;
+    let marker_types = config.markers.clone().unwrap_or_default().marker_types();
+
+    let (mut markers, territories, mut properties, mut urban_areas) =
+        source.geo_at(date).split_geo(&marker_types);
+
+    properties_filter.filter(&mut properties);
+    markers
+        .iter_mut()
+        .for_each(|m| properties_filter.filter(&mut m.properties));
+    for area in urban_areas.iter_mut() {
+        area.city = area.find_city(&markers).map(|m| m.title.clone());
+        properties_filter.filter(&mut area.properties);
+    }
+
+    let capitals = markers
+        .iter()
+        .filter(|m| m.ty.id == MarkerType::CAPITAL)
+        .count();
+    if capitals > 1 {
+        panic!("Country {id} has {capitals} capitals, expected at most one");
+    }
 
-    let (markers, territories) = geo.split_geo();
     let geo = dissolve_territories(territories);
 
+    let regions = source
+        .regions
+        .iter()
+        .map(|(region_id, region_config, geo)| {
+            let mut geo = geo.clone();
+            retain_valid_features(&mut geo, date);
+            let (_, territories, _, _) = geo.split_geo(&marker_types);
+
+            RegionData {
+                id: region_id.clone(),
+                config: region_config.clone(),
+                land: dissolve_territories(territories),
+            }
+        })
+        .collect();
+
     CountryData {
         id: id.clone(),
-        config: config.clone(),
+        config: source.config.clone(),
         land: geo,
         markers,
+        properties,
+        regions,
+        urban_areas,
+        children: vec![],
+        waters: MultiPolygon::new(vec![]),
     }
 }
 
@@ -73,48 +830,605 @@ pub fn diff_countries(countries: Vec<CountryData>) -> Vec<CountryData> {
     countries
 }
 
-pub fn hash_hex_color(s: String) -> String {
-    let hex_str = format!("{:x}", xxhash_rust::xxh3::xxh3_64(s.as_bytes()));
-
-    format!("#{}", hex_str.chars().take(6).collect::<String>())
+/// Clips regions and urban areas of every country to its land, dropping regions left without land
+pub fn clip_regions(countries: &mut [CountryData]) {
+    for country in countries.iter_mut() {
+        for region in country.regions.iter_mut() {
+            region.land = region.land.intersection(&country.land);
+        }
+        country.regions.retain(|region| !region.land.0.is_empty());
+        for area in country.urban_areas.iter_mut() {
+            area.land = area.land.intersection(&country.land);
+        }
+    }
 }
 
-pub fn is_match(tags: &Option<Vec<String>>, globs: &Vec<Glob>) -> bool {
-    if globs.len() == 0 {
-        return true;
+/// Checks that every marker lies inside its country's land and applies `config.outside` to those
+/// that don't. Returns a description of every misplaced marker.
+pub fn validate_markers(countries: &mut [CountryData], config: &MarkersConfig) -> Vec<String> {
+    let mut problems = vec![];
+
+    for country in countries.iter_mut() {
+        let land = &country.land;
+
+        country.markers.retain_mut(|marker| {
+            if land.intersects(&marker.coordinates) {
+                return true;
+            }
+
+            problems.push(format!(
+                "Marker \"{}\" ({}) lies outside of country {}",
+                marker.title, marker.ty.id, country.id
+            ));
+
+            match config.outside {
+                MarkerOutsideAction::Keep => true,
+                MarkerOutsideAction::Drop => false,
+                MarkerOutsideAction::Clip => match land.closest_point(&marker.coordinates) {
+                    Closest::Intersection(p) | Closest::SinglePoint(p) => {
+                        marker.coordinates = p;
+                        true
+                    }
+                    Closest::Indeterminate => false,
+                },
+            }
+        });
     }
 
-    match tags {
-        Some(tags) => {
-            let mut matches = false;
-            for glob in globs {
-                for tag in tags {
-                    if glob.is_match(tag.as_str()) {
-                        matches = true;
+    problems
+}
+
+/// Keeps countries with given ids and every country overlapping them, which is enough to diff
+/// borders of the given countries
+pub fn with_neighbors(countries: Vec<CountryData>, ids: &[String]) -> Vec<CountryData> {
+    let selected: Vec<(Option<Rect>, MultiPolygon)> = countries
+        .iter()
+        .filter(|c| ids.contains(&c.id))
+        .map(|c| (c.land.bounding_rect(), c.land.clone()))
+        .collect();
+
+    countries
+        .into_iter()
+        .filter(|c| {
+            if ids.contains(&c.id) {
+                return true;
+            }
+
+            let rect = c.land.bounding_rect();
+
+            selected.iter().any(|(selected_rect, land)| {
+                let near = match (rect, selected_rect) {
+                    (Some(a), Some(b)) => a.intersects(b),
+                    _ => false,
+                };
+
+                near && land.intersects(&c.land)
+            })
+        })
+        .collect()
+}
+
+/// Steps of growing waters from coasts when splitting overlaps, more steps are closer to the
+/// line of equal distance
+const WATERS_SPLIT_STEPS: u32 = 8;
+
+/// Territorial waters of every country: land buffered by `config.distance` without any land and
+/// clipped to `sea` if present
+pub fn generate_waters(
+    countries: &mut [CountryData],
+    config: &WatersConfig,
+    sea: Option<&MultiPolygon>,
+) {
+    let all_land = countries
+        .iter()
+        .fold(MultiPolygon::new(vec![]), |a, c| a.union(&c.land));
+
+    let claims: Vec<MultiPolygon> = countries
+        .iter()
+        .map(|c| {
+            if c.land.0.is_empty() {
+                return MultiPolygon::new(vec![]);
+            }
+
+            let claim = c.land.buffer(config.distance).difference(&all_land);
+
+            match sea {
+                Some(sea) => claim.intersection(sea),
+                None => claim,
+            }
+        })
+        .collect();
+
+    let waters = match config.overlap {
+        WatersOverlap::Priority => {
+            let mut waters = claims;
+
+            for i in 0..waters.len() {
+                for j in 0..waters.len() {
+                    if i != j {
+                        waters[i] = waters[i].difference(&waters[j]);
+                    }
+                }
+            }
+
+            waters
+        }
+        WatersOverlap::Split => {
+            let mut waters = vec![MultiPolygon::new(vec![]); claims.len()];
+            let mut claimed = MultiPolygon::new(vec![]);
+
+            for step in 1..=WATERS_SPLIT_STEPS {
+                let distance = config.distance * step as f64 / WATERS_SPLIT_STEPS as f64;
+
+                for (i, country) in countries.iter().enumerate() {
+                    if claims[i].0.is_empty() {
+                        continue;
                     }
+
+                    let reached = country
+                        .land
+                        .buffer(distance)
+                        .intersection(&claims[i])
+                        .difference(&claimed);
+
+                    claimed = claimed.union(&reached);
+                    waters[i] = waters[i].union(&reached);
                 }
             }
 
-            matches
+            waters
         }
-        None => true,
+    };
+
+    for (country, waters) in countries.iter_mut().zip(waters) {
+        country.waters = waters;
     }
 }
 
-pub fn rewrite_if_some<T>(value: Option<T>, rewrite: &mut T) {
+/// Serializes value as json. Canonical json has recursively sorted keys and ends with a newline
+pub fn to_json(value: &impl Serialize, format: JsonFormat, canonical: bool) -> String {
+    let value = serde_json::to_value(value).unwrap();
+    let value = if canonical { sort_keys(value) } else { value };
+
+    let mut json = match format {
+        JsonFormat::Minified => serde_json::to_string(&value).unwrap(),
+        JsonFormat::Pretty => serde_json::to_string_pretty(&value).unwrap(),
+    };
+
+    if canonical {
+        json.push('\n');
+    }
+
+    json
+}
+
+/// Sorts keys explicitly, as `serde_json::Map` keeps insertion order when any dependency enables
+/// its `preserve_order` feature
+fn sort_keys(value: serde_json::Value) -> serde_json::Value {
     match value {
-        Some(value) => {
-            *rewrite = value;
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<_> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            entries
+                .into_iter()
+                .map(|(k, v)| (k, sort_keys(v)))
+                .collect::<serde_json::Map<_, _>>()
+                .into()
         }
-        None => {}
+        serde_json::Value::Array(values) => values.into_iter().map(sort_keys).collect(),
+        value => value,
+    }
+}
+
+pub fn compress(contents: &[u8], compression: Compression) -> Vec<u8> {
+    match compression {
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(vec![], flate2::Compression::best());
+            encoder.write_all(contents).unwrap();
+            encoder.finish().unwrap()
+        }
+        Compression::Brotli => {
+            let mut compressed = vec![];
+            let params = BrotliEncoderParams {
+                quality: 11,
+                ..Default::default()
+            };
+            brotli::BrotliCompress(&mut &contents[..], &mut compressed, &params).unwrap();
+            compressed
+        }
+    }
+}
+
+/// Decimal places kept in output coordinates, about 1 cm
+const COORDINATE_PRECISION: i32 = 7;
+
+/// Rounds coordinates of all features, so floating point noise of boolean operations doesn't
+/// leak into generated files
+pub fn snap_coordinates(collection: &mut FeatureCollection) {
+    fn snap_position(position: &mut [f64]) {
+        let scale = 10f64.powi(COORDINATE_PRECISION);
+        position
+            .iter_mut()
+            .for_each(|c| *c = (*c * scale).round() / scale);
+    }
+
+    fn snap_value(value: &mut Value) {
+        match value {
+            Value::Point(p) => snap_position(p),
+            Value::MultiPoint(ps) | Value::LineString(ps) => {
+                ps.iter_mut().for_each(|p| snap_position(p))
+            }
+            Value::MultiLineString(ls) | Value::Polygon(ls) => {
+                ls.iter_mut().flatten().for_each(|p| snap_position(p))
+            }
+            Value::MultiPolygon(polygons) => polygons
+                .iter_mut()
+                .flatten()
+                .flatten()
+                .for_each(|p| snap_position(p)),
+            Value::GeometryCollection(geometries) => {
+                geometries.iter_mut().for_each(|g| snap_value(&mut g.value))
+            }
+        }
+    }
+
+    for feature in collection.features.iter_mut() {
+        if let Some(geometry) = feature.geometry.as_mut() {
+            snap_value(&mut geometry.value);
+        }
+    }
+}
+
+pub fn hash_hex(contents: &[u8]) -> String {
+    format!("{:016x}", xxhash_rust::xxh3::xxh3_64(contents))
+}
+
+/// Inserts hash before the extension: `geo.geojson` -> `geo.3fa2c1d0.geojson`
+pub fn hashed_file_name(name: &str, hash: &str) -> String {
+    let short_hash = &hash[..8];
+
+    match name.split_once('.') {
+        Some((stem, extension)) => format!("{stem}.{short_hash}.{extension}"),
+        None => format!("{name}.{short_hash}"),
+    }
+}
+
+/// Merges land and markers of countries with `ids` into the country `into`, removing them
+pub fn merge_countries(
+    countries: Vec<CountryData>,
+    ids: &[String],
+    into: &str,
+) -> Vec<CountryData> {
+    let (members, mut countries): (Vec<CountryData>, Vec<CountryData>) = countries
+        .into_iter()
+        .partition(|c| ids.contains(&c.id) && c.id != into);
+
+    let target = match countries.iter_mut().find(|c| c.id == into) {
+        Some(target) => target,
+        None => panic!("Country {into} to merge into is not included in the processing item"),
+    };
+
+    for member in members {
+        target.land = target.land.union(&member.land);
+        target.markers.extend(member.markers);
+        target.regions.extend(member.regions);
+        target.urban_areas.extend(member.urban_areas);
+    }
+
+    countries
+}
+
+pub fn hash_hex_color(s: String) -> String {
+    let hex_str = format!("{:x}", xxhash_rust::xxh3::xxh3_64(s.as_bytes()));
+
+    format!("#{}", hex_str.chars().take(6).collect::<String>())
+}
+
+pub fn rewrite_if_some<T>(value: Option<T>, rewrite: &mut T) {
+    if let Some(value) = value {
+        *rewrite = value;
     }
 }
 
 pub fn rewrite_if_some_option<T>(value: Option<T>, rewrite: &mut Option<T>) {
-    match value {
-        Some(value) => {
-            *rewrite = Some(value);
+    if let Some(value) = value {
+        *rewrite = Some(value);
+    }
+}
+
+/// Removes tags matching `remove` globs, then adds `add` tags which are not present yet
+pub fn rewrite_tags(
+    remove: Option<Vec<String>>,
+    add: Option<Vec<String>>,
+    tags: &mut Option<Vec<String>>,
+) {
+    if remove.is_none() && add.is_none() {
+        return;
+    }
+
+    let mut new_tags = tags.take().unwrap_or_default();
+
+    if let Some(remove) = remove {
+        let globs: Vec<Glob> = remove.iter().map(|g| Glob::new(g).unwrap()).collect();
+        new_tags.retain(|tag| !globs.iter().any(|g| g.is_match(tag.as_str())));
+    }
+
+    for tag in add.unwrap_or_default() {
+        if !new_tags.contains(&tag) {
+            new_tags.push(tag);
+        }
+    }
+
+    *tags = Some(new_tags);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(s: &str) -> toml::Table {
+        toml::from_str(s).unwrap()
+    }
+
+    fn processing(config: &str) -> Vec<toml::Value> {
+        resolve_processing(table(config))["processing"]
+            .as_array()
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn merges_tables_and_concatenates_arrays_of_tables() {
+        let mut base = table(
+            r#"
+            date = 1900
+            tags = ["a"]
+            waters = { distance = 0.2, overlap = "split" }
+            countries_rewrite = [{ countries = ["a"] }]
+            "#,
+        );
+
+        merge_toml(
+            &mut base,
+            table(
+                r#"
+                date = 1914
+                tags = ["b"]
+                waters = { distance = 0.5 }
+                countries_rewrite = [{ countries = ["b"] }]
+                "#,
+            ),
+        );
+
+        assert_eq!(
+            base,
+            table(
+                r#"
+                date = 1914
+                tags = ["b"]
+                waters = { distance = 0.5, overlap = "split" }
+                countries_rewrite = [{ countries = ["a"] }, { countries = ["b"] }]
+                "#,
+            )
+        );
+    }
+
+    #[test]
+    fn empty_array_clears_inherited_tables() {
+        let mut base = table(r#"countries_rewrite = [{ countries = ["a"] }]"#);
+        merge_toml(&mut base, table("countries_rewrite = []"));
+
+        assert_eq!(base, table("countries_rewrite = []"));
+    }
+
+    #[test]
+    fn resolves_defaults_and_extends() {
+        let items = processing(
+            r#"
+            [processing_defaults]
+            show_markers = false
+            canonical = true
+
+            [[processing]]
+            name = "world"
+            output_folder = "out/world"
+            canonical = false
+
+            [[processing]]
+            name = "europe"
+            extends = "world"
+            output_folder = "out/europe"
+            tags = ["europe"]
+            "#,
+        );
+
+        assert_eq!(
+            items[0],
+            toml::Value::Table(table(
+                r#"
+                name = "world"
+                output_folder = "out/world"
+                show_markers = false
+                canonical = false
+                "#
+            ))
+        );
+        // Defaults apply only through the extended item
+        assert_eq!(
+            items[1],
+            toml::Value::Table(table(
+                r#"
+                name = "europe"
+                extends = "world"
+                output_folder = "out/europe"
+                show_markers = false
+                canonical = false
+                tags = ["europe"]
+                "#
+            ))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "processing item a extends itself")]
+    fn extends_cycle() {
+        processing(
+            r#"
+            [[processing]]
+            name = "a"
+            extends = "b"
+            output_folder = "a"
+
+            [[processing]]
+            name = "b"
+            extends = "a"
+            output_folder = "b"
+            "#,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "no processing item named c to extend")]
+    fn extends_unknown_item() {
+        processing(
+            r#"
+            [[processing]]
+            extends = "c"
+            output_folder = "a"
+            "#,
+        );
+    }
+
+    fn collection(properties: &[&str]) -> FeatureCollection {
+        let features: Vec<String> = properties
+            .iter()
+            .map(|p| {
+                format!(
+                    r#"{{"type": "Feature", "properties": {p}, "geometry":
+                        {{"type": "Polygon", "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 0]]]}}}}"#
+                )
+            })
+            .collect();
+
+        format!(
+            r#"{{"type": "FeatureCollection", "features": [{}]}}"#,
+            features.join(",")
+        )
+        .parse()
+        .unwrap()
+    }
+
+    fn date(s: &str) -> PartialDate {
+        s.parse().unwrap()
+    }
+
+    fn source() -> CountrySource {
+        CountrySource {
+            id: "a".to_owned(),
+            config: toml::from_str(
+                r#"
+                name = "A"
+                description = ""
+                foundation_date = "1800"
+                dissolution_date = "1950"
+                flag = ""
+                fill = ""
+                stroke = ""
+                "#,
+            )
+            .unwrap(),
+            versions: vec![
+                (None, collection(&[r#"{"v": "original"}"#])),
+                (
+                    Some(date("1920-01-01")),
+                    collection(&[r#"{"v": "1920"}"#, r#"{"valid_to": "1930"}"#]),
+                ),
+                (Some(date("1939-09")), collection(&[r#"{"v": "1939"}"#])),
+            ],
+            regions: vec![(
+                "north".to_owned(),
+                toml::from_str(r#"name = "North""#).unwrap(),
+                collection(&[r#"{"valid_from": "1925"}"#]),
+            )],
         }
-        None => {}
+    }
+
+    #[test]
+    fn history_files_are_in_effect_from_their_date() {
+        let source = source();
+        let version = |d: &str| source.version_at(Some(&date(d)));
+
+        assert_eq!(version("1850"), 0);
+        assert_eq!(version("1919-12-31"), 0);
+        assert_eq!(version("1920"), 1);
+        assert_eq!(version("1939-08"), 1);
+        assert_eq!(version("1939-09-01"), 2);
+        assert_eq!(version("2000"), 2);
+        // Latest borders without date
+        assert_eq!(source.version_at(None), 2);
+
+        let geo = source.geo_at(Some(&date("1921")));
+        assert_eq!(
+            geo.features[0].property("v"),
+            Some(&serde_json::json!("1920"))
+        );
+    }
+
+    #[test]
+    fn state_changes_with_versions_features_regions_and_existence() {
+        let source = source();
+        let state = |d: &str| source.state_at(Some(&date(d)));
+
+        assert_eq!(state("1700"), None);
+        assert_eq!(state("1960"), None);
+        assert_eq!(state("1850"), state("1900"));
+        assert_ne!(state("1900"), state("1920"));
+        // Region becomes valid
+        assert_ne!(state("1921"), state("1926"));
+        // Feature becomes invalid
+        assert_ne!(state("1926"), state("1931"));
+        assert_eq!(state("1931"), state("1935"));
+        assert_eq!(
+            state("1931"),
+            Some((1, vec![true, false], vec![vec![true]]))
+        );
+    }
+
+    #[test]
+    fn canonical_json_has_sorted_keys() {
+        #[derive(Serialize)]
+        struct Unsorted {
+            b: u8,
+            a: Vec<serde_json::Value>,
+        }
+
+        let value = Unsorted {
+            b: 1,
+            a: vec![serde_json::json!({ "z": 1, "y": { "d": 2, "c": 3 } })],
+        };
+
+        assert_eq!(
+            to_json(&value, JsonFormat::Minified, true),
+            "{\"a\":[{\"y\":{\"c\":3,\"d\":2},\"z\":1}],\"b\":1}\n"
+        );
+    }
+
+    #[test]
+    fn snaps_coordinates_of_all_geometries() {
+        let mut collection: FeatureCollection = r#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature", "properties": {}, "geometry": {"type": "Polygon", "coordinates":
+                [[[0, 0], [0.3333332973697667, 0], [1.7777776998700623, 1], [0, 0]]]}},
+            {"type": "Feature", "properties": {}, "geometry": {"type": "Point", "coordinates":
+                [-12.00000004, 5.5]}}
+        ]}"#
+        .parse()
+        .unwrap();
+
+        snap_coordinates(&mut collection);
+
+        let json = serde_json::to_string(&collection).unwrap();
+        assert!(json.contains("[0.3333333,0.0],[1.7777777,1.0]"), "{json}");
+        assert!(json.contains("[-12.0,5.5]"), "{json}");
     }
 }